@@ -34,6 +34,8 @@ pub struct ApiCard
 , pub image_blue: String
 , pub stats: ApiStatsWrapper
 , pub r#type: ApiType
+// Only FF8-style cardlists have elements
+, #[serde(default)] pub element: Option<String>
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiData {
@@ -68,6 +70,7 @@ fn translate_card(api_card:ApiCard) -> Card {
   , stars: api_card.stars
   , name: api_card.name
   , stats: translate_stats(api_card.stats, api_card.r#type)
+  , element: api_card.element.and_then(|element| element.parse().ok())
   }
 }
 
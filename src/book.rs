@@ -0,0 +1,244 @@
+use std::{collections::HashMap, hash::Hasher};
+use fnv::FnvHasher;
+use itertools::Itertools;
+use mcts::GameState;
+use serde::{Serialize, Deserialize};
+
+use crate::{game::*, saved, types::*};
+
+pub const BOOK_FILENAME: &str = "openings.json";
+
+// Bumped whenever the key or entry format changes
+const BOOK_VERSION: u32 = 2;
+
+// Rules that leave the opening to chance, so there's no fixed position to prepare for. Under Three Open,
+// which of the opponent's cards show varies from game to game.
+pub const UNBOOKABLE_RULES: [Rule;7] = [Rule::Roulette, Rule::Random, Rule::Draft, Rule::Swap, Rule::Chaos, Rule::Elemental, Rule::ThreeOpen];
+
+// A stored move: the hand position and square to play, and how the search rated it. The square is stored
+// in the position's canonical orientation, so one entry serves every rotation and reflection of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BookMove
+{ pub hand_idx: usize
+, pub row: usize
+, pub col: usize
+, pub value: f32
+}
+impl BookMove {
+  pub fn from_move(game:&Game, symmetry:Symmetry, user_move:&Move, value:f32) -> Option<BookMove> {
+    let hand_idx = game.hands[&user_move.player].0.iter().position(|slot| slot.card() == Some(&user_move.card))?;
+    let coords = symmetry.apply(&game.board, &user_move.coords);
+    Some(BookMove { hand_idx, row: coords.row, col: coords.col, value })
+  }
+
+  // None if the move isn't legal here, which would mean the entry was stored for a different position
+  pub fn to_move(&self, game:&Game, symmetry:Symmetry) -> Option<Move> {
+    let canonical_coords = Coord { row: self.row, col: self.col };
+    if !game.board.contains(&canonical_coords) {
+      return None
+    }
+    let coords = symmetry.invert(&game.board, &canonical_coords);
+    let is_open = game.board.contains(&coords) && game.board.square(&coords).0.is_none();
+    let is_playable = game.playable_card_idxs().contains(&self.hand_idx);
+    let card = game.hands[&game.turn].0.get(self.hand_idx)?.card()?.clone();
+    (is_open && is_playable).then(|| Move::new(card, coords, game.turn))
+  }
+}
+
+// Precomputed first moves for the decks we play over and over. Positions are keyed by FNV over `Game::canonical_form`,
+// which covers the board, both hands and the rules, plus the opponent's deck while their hand is hidden.
+#[derive(Debug, Default)]
+pub struct OpeningBook
+{ entries: HashMap<u64, BookMove>
+}
+impl OpeningBook {
+  pub fn new() -> OpeningBook {
+    OpeningBook::default()
+  }
+
+  pub fn load(filename:&str) -> Result<OpeningBook, String> {
+    let entries = saved::load(filename, BOOK_VERSION, "opening book")?.unwrap_or_default();
+    Ok(OpeningBook { entries })
+  }
+
+  pub fn save(&self, filename:&str) -> Result<(), String> {
+    saved::save(filename, BOOK_VERSION, &self.entries, "opening book")
+  }
+
+  pub fn positions(&self) -> usize {
+    self.entries.len()
+  }
+
+  // Which hand slot a hidden card was played from is arbitrary, so hidden hands are keyed with their
+  // empty slots first, and by the deck their cards come from. Also returns the symmetry to the canonical orientation.
+  fn key(game:&Game, pool:&[Card]) -> (u64, Symmetry) {
+    let mut game = game.clone();
+    for hand in game.hands.values_mut().filter(|hand| hand.0.iter().any(HandSlot::is_hidden)) {
+      hand.0.sort_by_key(|slot| !slot.is_empty());
+    }
+    let (position, symmetry) = game.canonical_form();
+    let mut hasher = FnvHasher::default();
+    hasher.write(&position);
+    if game.has_hidden_cards() {
+      let deck = pool.iter().map(|card| {
+        let mut bytes = Vec::new();
+        write_card_face(card, Symmetry::default(), &mut bytes);
+        bytes
+      });
+      deck.sorted().for_each(|card| hasher.write(&card));
+    }
+    (hasher.finish(), symmetry)
+  }
+
+  // The stored move for this position, if there is one. `pool` is the opponent's deck, as given to the search.
+  pub fn lookup(&self, game:&Game, pool:&[Card]) -> Option<Move> {
+    if game.rules.contains(Rule::Chaos) {
+      return None
+    }
+    let (key, symmetry) = OpeningBook::key(game, pool);
+    self.entries.get(&key)?.to_move(game, symmetry)
+  }
+
+  // Walks every line in which `game.my_color` has made fewer than `my_moves` moves, storing `search`'s choice
+  // on each of our turns that isn't in the book yet, and following every reply the opponent could make with
+  // the cards in `pool`. Returns how many positions were added.
+  pub fn extend<F: FnMut(&Game) -> Option<(Move, f32)>>(&mut self, game:&Game, pool:&[Card], my_moves:usize, search:&mut F) -> usize {
+    if my_moves == 0 || game.get_result().is_some() {
+      return 0
+    }
+    if game.turn != game.my_color {
+      return opponent_replies(game, pool).iter().map(|reply| self.extend(reply, pool, my_moves, search)).sum()
+    }
+
+    let (key, symmetry) = OpeningBook::key(game, pool);
+    let (entry, added) = match self.entries.get(&key) {
+      Some(entry) => (entry.clone(), 0),
+      None => {
+        let Some(entry) = search(game).and_then(|(user_move, value)| BookMove::from_move(game, symmetry, &user_move, value)) else { return 0 };
+        self.entries.insert(key, entry.clone());
+        (entry, 1)
+      }
+    };
+    let mut child = game.clone();
+    match entry.to_move(game, symmetry).and_then(|user_move| child.make_move(&user_move)) {
+      Some(MoveResult::Finished(_) | MoveResult::NextRound) | None => added,
+      Some(_) => added + self.extend(&child, pool, my_moves - 1, search)
+    }
+  }
+}
+
+// Every position the opponent's move could lead to: their known cards, and each unplayed card of `pool`
+// revealed from their first hidden slot
+fn opponent_replies(game:&Game, pool:&[Card]) -> Vec<Game> {
+  let player = game.turn;
+  let used_cards =
+    game.hands.values()
+    .flat_map(|hand| hand.0.iter().filter_map(|slot| slot.card().map(|card| card.card.clone())))
+    .chain(game.board.squares.iter().flatten().filter_map(|square| square.0.as_ref().map(|card| card.card.clone())))
+    .collect::<Vec<Card>>();
+
+  let revealed = match game.hands[&player].0.iter().position(HandSlot::is_hidden) {
+    Some(idx) =>
+      pool.iter()
+      .filter(|card| !used_cards.contains(card))
+      .unique()
+      .map(|card| {
+        let mut revealed = game.clone();
+        revealed.reveal_card(GameCard { card: card.clone(), player: None, owner: None }, player, idx);
+        (revealed, Some(card.clone()))
+      })
+      .collect(),
+    None => Vec::new()
+  };
+
+  std::iter::once((game.clone(), None))
+    .chain(revealed)
+    .flat_map(|(position, revealed_card)| {
+      position.available_moves()
+        .into_iter()
+        .filter_map(|action| match action {
+          Action::Play(user_move) if revealed_card.as_ref().is_none_or(|card| user_move.card.card == *card) => Some(user_move),
+          _ => None
+        })
+        .filter_map(move |user_move| {
+          let mut child = position.clone();
+          child.make_move(&user_move).map(|_| child)
+        })
+        .collect::<Vec<Game>>()
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn card(top:usize, right:usize, bottom:usize, left:usize) -> GameCard {
+    let stats = CardStats { top, right, bottom, left, tribe: None };
+    GameCard { card: Card { name: format!("{} {} {} {}", top, right, bottom, left), id: 0, stars: 1, stats, element: None }, player: None, owner: None }
+  }
+
+  // Blue to move, after Blue's first card in the corner and Red's beside it
+  fn opening(red_hand:Vec<HandSlot>) -> Game {
+    let mut game = Game::new(Player::Blue, Player::Blue, RuleSet::new());
+    for (player, placed, coords) in [(Player::Blue, card(1, 2, 3, 4), Coord::new(0, 0)), (Player::Red, card(5, 6, 7, 8), Coord::new(0, 1))] {
+      *game.board.square_mut(&coords) = Square(Some(GameCard { player: Some(player), owner: Some(player), ..placed }), None);
+    }
+    game.add_card_to_hand(card(9, 1, 2, 3), Player::Blue).add_card_to_hand(card(3, 3, 9, 1), Player::Blue);
+    game.hands.get_mut(&Player::Red).unwrap().0 = red_hand;
+    game
+  }
+
+  fn store(book:&mut OpeningBook, game:&Game, pool:&[Card], hand_idx:usize, coords:Coord) -> usize {
+    book.extend(game, pool, 1, &mut |game:&Game| {
+      let card = game.hands[&game.turn].0[hand_idx].card().cloned()?;
+      Some((Move::new(card, coords, game.turn), 0.5))
+    })
+  }
+
+  #[test]
+  fn stored_moves_are_looked_up() {
+    let red_hand = vec![HandSlot::Known(card(4, 4, 4, 4)), HandSlot::Known(card(2, 8, 2, 8)), HandSlot::Empty];
+    let game = opening(red_hand);
+    let mut book = OpeningBook::new();
+    assert_eq!(store(&mut book, &game, &[], 1, Coord::new(2, 1)), 1);
+    assert_eq!(store(&mut book, &game, &[], 0, Coord::new(2, 2)), 0);
+
+    let user_move = book.lookup(&game, &[]).unwrap();
+    assert_eq!(user_move.coords, Coord::new(2, 1));
+    assert_eq!(Some(&user_move.card), game.hands[&Player::Blue].0[1].card());
+    assert_eq!(book.positions(), 1);
+  }
+
+  #[test]
+  fn rotated_positions_share_an_entry() {
+    let red_hand = vec![HandSlot::Known(card(4, 4, 4, 4)), HandSlot::Known(card(2, 8, 2, 8)), HandSlot::Empty];
+    let game = opening(red_hand);
+    let mut book = OpeningBook::new();
+    assert_eq!(store(&mut book, &game, &[], 1, Coord::new(2, 1)), 1);
+
+    for symmetry in Symmetry::all(&game.board) {
+      let turned = game.transformed(symmetry);
+      let user_move = book.lookup(&turned, &[]).unwrap();
+      assert_eq!(user_move.coords, symmetry.apply(&game.board, &Coord::new(2, 1)), "{:?}", symmetry);
+      assert_eq!(Some(&user_move.card), turned.hands[&Player::Blue].0[1].card());
+    }
+    assert_eq!(book.positions(), 1);
+  }
+
+  #[test]
+  fn hidden_slots_match_whichever_was_played() {
+    let pool = [card(4, 4, 4, 4), card(2, 8, 2, 8), card(6, 1, 6, 1)].map(|card| card.card);
+    let hidden = |empty_idx:usize| (0..3).map(|idx| if idx == empty_idx { HandSlot::Empty } else { HandSlot::Unknown }).collect::<Vec<HandSlot>>();
+    let mut book = OpeningBook::new();
+    assert_eq!(store(&mut book, &opening(hidden(0)), &pool, 0, Coord::new(1, 1)), 1);
+
+    for empty_idx in [1, 2] {
+      let game = opening(hidden(empty_idx));
+      assert_eq!(store(&mut book, &game, &pool, 1, Coord::new(2, 2)), 0);
+      assert_eq!(book.lookup(&game, &pool).map(|user_move| user_move.coords), Some(Coord::new(1, 1)));
+    }
+    // A different deck is a different position
+    assert_eq!(book.lookup(&opening(hidden(0)), &pool[..2]), None);
+  }
+}
@@ -1,7 +1,7 @@
 
 use itertools::Itertools;
 
-use crate::{types::Card, api};
+use crate::{types::Card, api, game::{Rule, RuleSet}};
 
 ///These categories are meta-specific; 
 /// - Three star cards can be "corner 8s"(cards with two adjacent sides having 8, the highest value possible on a 3*)
@@ -26,12 +26,23 @@ pub enum ThreeStarMetaClasses
 , TripleB
 }
 
-pub fn sum_value_scores(card:&Card) -> usize {
-  card.stats.top + card.stats.right + card.stats.bottom + card.stats.left
+/// How strong a side value is under the active rules; with Reverse, a 1 is as good as an A normally is.
+pub fn side_strength(value:usize, rules:&RuleSet) -> usize {
+  if rules.contains(Rule::Reverse) { 11 - value } else { value }
 }
 
-pub fn square_value_scores(card:&Card) -> usize {
-  card.stats.top.pow(2) + card.stats.right.pow(2) + card.stats.bottom.pow(2) + card.stats.left.pow(2)
+pub fn sum_value_scores(card:&Card, rules:&RuleSet) -> usize {
+  [card.stats.top, card.stats.right, card.stats.bottom, card.stats.left]
+    .into_iter()
+    .map(|value| side_strength(value, rules))
+    .sum()
+}
+
+pub fn square_value_scores(card:&Card, rules:&RuleSet) -> usize {
+  [card.stats.top, card.stats.right, card.stats.bottom, card.stats.left]
+    .into_iter()
+    .map(|value| side_strength(value, rules).pow(2))
+    .sum()
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::collections::HashMap;
+use rand::{Rng, SeedableRng, StdRng};
+use serde::{Serialize, Deserialize};
+
+use crate::{api, types::{Card, Element}, game::{Game, GameCard, Player}, trade::Trade};
+
+// A saved card: its id, or its id and element for cards that have one under Elemental
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedCard
+{ Id(usize)
+, WithElement { id: usize, element: String }
+}
+
+// The cards a player owns; saved to disk as a JSON list of card ids, resolved against the cardlist
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Collection
+{ pub cards: Vec<Card>
+}
+impl Collection {
+  pub fn load(filename:&str) -> Result<Collection, String> {
+    let contents = std::fs::read_to_string(filename).map_err(|e| format!("Failed to read collection {}: {}", filename, e))?;
+    let saved : Vec<SavedCard> = serde_json::from_str(&contents).map_err(|e| format!("Failed to parse collection {}: {}", filename, e))?;
+    let cardlist = api::read_cardlist();
+    let cards = 
+      saved
+      .iter()
+      .map(|saved| {
+        let (id, element) = match saved {
+          SavedCard::Id(id) => (*id, None),
+          SavedCard::WithElement { id, element } => (*id, Some(element.parse::<Element>()?))
+        };
+        let card = cardlist.iter().find(|card| card.id == id).cloned().ok_or(format!("Collection {} contains unknown card id {}", filename, id))?;
+        Ok(Card { element: element.or(card.element), ..card })
+      })
+      .collect::<Result<Vec<Card>, String>>()?;
+    Ok(Collection { cards })
+  }
+
+  pub fn save(&self, filename:&str) -> Result<(), String> {
+    let saved =
+      self.cards
+      .iter()
+      .map(|card| match card.element {
+        Some(element) => SavedCard::WithElement { id: card.id, element: format!("{:?}", element).to_lowercase() },
+        None => SavedCard::Id(card.id)
+      })
+      .collect::<Vec<SavedCard>>();
+    let contents = serde_json::to_string(&saved).map_err(|e| format!("Failed to serialize collection {}: {}", filename, e))?;
+    std::fs::write(filename, contents).map_err(|e| format!("Failed to write collection {}: {}", filename, e))
+  }
+
+  // Moves the cards `player` won and lost in a trade into and out of the collection
+  pub fn apply_trade(&mut self, trade:&Trade, player:Player, cardlist:&[Card]) -> Result<(), String> {
+    for card in trade.lost(player) {
+      let card = identify_card(card, cardlist)?;
+      match self.cards.iter().position(|owned| owned.id == card.id) {
+        Some(idx) => { self.cards.remove(idx); }
+        None => return Err(format!("Lost {}, but it isn't in the collection", card.name))
+      }
+    }
+    for card in trade.gained(player) {
+      self.cards.push(identify_card(card, cardlist)?);
+    }
+    Ok(())
+  }
+
+  // Deals distinct cards from the collection, as the Random rule does
+  pub fn deal<R: Rng>(&self, rng:&mut R, n:usize) -> Result<Vec<Card>, String> {
+    rand::seq::sample_iter(rng, self.cards.iter().cloned(), n)
+      .map_err(|cards| format!("The collection has only {} cards, but a hand needs {}", cards.len(), n))
+  }
+}
+
+// Cards typed in during a game have no id, so match them to the cardlist by their sides
+pub fn identify_card(card:&Card, cardlist:&[Card]) -> Result<Card, String> {
+  if let Some(known) = cardlist.iter().find(|known| card.id != 0 && known.id == card.id) {
+    return Ok(known.clone())
+  }
+  let sides = |card:&Card| (card.stats.top, card.stats.right, card.stats.bottom, card.stats.left);
+  match cardlist.iter().filter(|known| sides(known) == sides(card)).collect::<Vec<&Card>>()[..] {
+    [known] => Ok(known.clone()),
+    [] => Err(format!("No card in the cardlist matches {} {:?}", card.name, sides(card))),
+    _ => Err(format!("Several cards in the cardlist match {} {:?}, so it can't be identified", card.name, sides(card)))
+  }
+}
+
+// Records which cards were dealt from which seed, so a deal can be reproduced and inspected
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DealLog
+{ pub seed: usize
+, pub hands: Vec<(Player, Vec<Card>)>
+}
+impl fmt::Display for DealLog {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let hands = 
+      self.hands
+      .iter()
+      .map(|(player, cards)| format!("  {}: {}", player, cards.iter().map(|card| format!("{} (#{})", card.name, card.id)).collect::<Vec<String>>().join(", ")))
+      .collect::<Vec<String>>();
+    write!(f, "Deal with seed {}:\n{}", self.seed, hands.join("\n"))
+  }
+}
+
+// The Random rule: each player's hand is dealt from their collection instead of chosen
+pub fn deal_random_hands(game:&mut Game, collections:&HashMap<Player, Collection>, seed:usize) -> Result<DealLog, String> {
+  let mut rng = StdRng::from_seed(&[seed][..]);
+  let mut hands = Vec::new();
+  for player in [Player::Blue, Player::Red] {
+    let cards = collections[&player].deal(&mut rng, game.hand_size()).map_err(|e| format!("Can't deal {}'s hand: {}", player, e))?;
+    for card in &cards {
+      game.add_card_to_hand(GameCard { card: card.clone(), player: None, owner: None }, player);
+    }
+    hands.push((player, cards));
+  }
+  Ok(DealLog { seed, hands })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use itertools::Itertools;
+  use crate::{game::RuleSet, types::CardStats};
+
+  fn collection(size:usize) -> Collection {
+    let card = |id:usize| Card { name: format!("Card {}", id), id, stars: 1, stats: CardStats { top: 1, right: 2, bottom: 3, left: 4, tribe: None }, element: None };
+    Collection { cards: (1..=size).map(card).collect() }
+  }
+
+  #[test]
+  fn deals_distinct_cards() {
+    let cards = collection(8).deal(&mut StdRng::from_seed(&[1][..]), 5).unwrap();
+    assert_eq!(cards.len(), 5);
+    assert!(cards.iter().map(|card| card.id).all_unique());
+  }
+
+  #[test]
+  fn rejects_undersized_collections() {
+    assert!(collection(3).deal(&mut StdRng::from_seed(&[1][..]), 5).is_err());
+    let collections = HashMap::from([(Player::Blue, collection(5)), (Player::Red, collection(4))]);
+    let mut game = Game::new(Player::Blue, Player::Blue, RuleSet::new());
+    assert!(deal_random_hands(&mut game, &collections, 0).is_err());
+  }
+
+  #[test]
+  fn saves_card_elements() {
+    let filename = std::env::temp_dir().join(format!("triple_triad_collection_{}.json", std::process::id()));
+    let filename = filename.to_str().unwrap();
+    let cardlist = api::read_cardlist();
+    let collection = Collection { cards: vec![Card { element: Some(Element::Fire), ..cardlist[0].clone() }, cardlist[1].clone()] };
+    collection.save(filename).unwrap();
+    assert_eq!(std::fs::read_to_string(filename).unwrap(), format!("[{{\"id\":{},\"element\":\"fire\"}},{}]", cardlist[0].id, cardlist[1].id));
+    assert_eq!(Collection::load(filename).unwrap(), collection);
+    std::fs::remove_file(filename).unwrap();
+  }
+}
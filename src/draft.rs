@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use rand::Rng;
+
+use crate::{types::Card, game::*, simulation::self_play};
+
+// One card is drafted from each star tier, so a drafted hand always has one card of every rarity
+const STAR_TIERS: usize = 5;
+
+// Players alternately pick from a shared pool, one tier at a time, snaking the first pick between tiers
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Draft
+{ pub tiers: Vec<Vec<Card>>
+, pub picks: HashMap<Player, Vec<Card>>
+, pub turn: Player
+}
+impl Draft {
+  // Draws `cards_per_tier` cards of each star rating from the cardlist
+  pub fn new<R: Rng>(cardlist:&[Card], cards_per_tier:usize, first_picker:Player, rng:&mut R) -> Draft {
+    assert!(cards_per_tier >= 2, "{} cards per tier (both players pick from every tier)", cards_per_tier);
+    let tiers = 
+      (1..=STAR_TIERS)
+      .map(|stars| {
+        let tier = cardlist.iter().filter(|card| card.stars == stars).cloned();
+        rand::seq::sample_iter(rng, tier, cards_per_tier).unwrap_or_else(|cards| cards)
+      })
+      .collect();
+    Draft 
+      { tiers
+      , picks: HashMap::from([(Player::Blue, Vec::new()), (Player::Red, Vec::new())])
+      , turn: first_picker
+      }
+  }
+
+  // Tier index (stars - 1) the current player is picking from
+  pub fn current_tier(&self) -> Option<usize> {
+    let tier = self.picks[&self.turn].len();
+    (tier < self.tiers.len()).then_some(tier)
+  }
+
+  pub fn available(&self) -> &[Card] {
+    match self.current_tier() {
+      Some(tier) => &self.tiers[tier],
+      None => &[]
+    }
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.picks.values().all(|picks| picks.len() >= self.tiers.len())
+  }
+
+  pub fn pick(&mut self, idx:usize) -> Option<&mut Draft> {
+    let tier = self.current_tier()?;
+    if idx >= self.tiers[tier].len() {
+      return None
+    }
+    let card = self.tiers[tier].remove(idx);
+    self.picks.get_mut(&self.turn).unwrap().push(card);
+    // Whoever picks last in a tier picks first in the next
+    if self.picks[&self.turn.other()].len() < self.picks[&self.turn].len() {
+      self.turn = self.turn.other();
+    }
+    Some(self)
+  }
+
+  pub fn pick_randomly<R: Rng>(&mut self, rng:&mut R) -> Option<&mut Draft> {
+    let idx = rng.gen_range(0, self.available().len().max(1));
+    self.pick(idx)
+  }
+
+  pub fn into_game(self, first_player:Player, my_color:Player, rules:RuleSet) -> Game {
+    let mut game = Game::new(first_player, my_color, rules);
+    for (player, picks) in self.picks {
+      for card in picks {
+        game.add_card_to_hand(GameCard { card, player: None, owner: None }, player);
+      }
+    }
+    game
+  }
+}
+
+// Values each available pick for the player on the clock: the draft is finished with random picks
+// and played out by the AI `rollouts` times per candidate, scoring +1 for a win and -1 for a loss
+pub fn evaluate_picks<R: Rng>(draft:&Draft, rules:RuleSet, rng:&mut R, rollouts:usize, playouts:u64) -> Result<Vec<f32>, String> {
+  let picker = draft.turn;
+  (0..draft.available().len())
+    .map(|idx| {
+      let total = 
+        (0..rollouts)
+        .map(|_| {
+          let mut rollout = draft.clone();
+          rollout.pick(idx);
+          while !rollout.is_finished() {
+            rollout.pick_randomly(rng);
+          }
+          let first_player = if rng.gen() { Player::Blue } else { Player::Red };
+          let mut game = rollout.into_game(first_player, picker, rules);
+          if game.rules.contains(Rule::Elemental) {
+            game.assign_random_elements(rng);
+          }
+          match self_play(&mut game, rng, playouts)? {
+            GameResult::Win(winner) if winner == picker => Ok(1.0),
+            GameResult::Win(_) => Ok(-1.0),
+            GameResult::Draw => Ok(0.0)
+          }
+        })
+        .sum::<Result<f32, String>>()?;
+      Ok(total / rollouts.max(1) as f32)
+    })
+    .collect()
+}
+
+// The drafting agent: takes the pick with the best rollout value
+pub fn choose_pick<R: Rng>(draft:&Draft, rules:RuleSet, rng:&mut R, rollouts:usize, playouts:u64) -> Result<Option<usize>, String> {
+  let values = evaluate_picks(draft, rules, rng, rollouts, playouts)?;
+  Ok(values
+    .into_iter()
+    .enumerate()
+    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    .map(|(idx, _)| idx))
+}
@@ -1,19 +1,26 @@
-use std::{hash::{Hash, Hasher}, collections::hash_map::DefaultHasher};
-use std::{fmt, collections::HashMap};
+use std::{hash::{Hash, Hasher}, collections::hash_map::DefaultHasher, sync::Arc};
+use std::{fmt, collections::HashMap, str::FromStr};
 use colored::Colorize;
+use fnv::FnvHasher;
 use itertools::*;
 
-use mcts::{*, tree_policy::UCTPolicy, transposition_table::{ApproxTable, TranspositionHash}};
+use mcts::{*, tree_policy::{TreePolicy, PolicyRng}, transposition_table::{ApproxTable, TranspositionHash}};
+use rand::Rng;
 
-use crate::types::*;
+use crate::{types::*, trade::{TradeRule, projected_trade}, rules::{GameRule, HouseRules, RoundEnd, built_in_rules}, tablebase::Tablebase};
 
 #[allow(dead_code)]
-pub fn valid_hand_idx(hand: &Hand, s:&str) -> Result<usize, String>{
+pub fn valid_hand_idx(game: &Game, s:&str) -> Result<usize, String>{
+  let hand = &game.hands[&game.turn];
+  let playable_idxs = game.playable_card_idxs();
   match s.parse::<usize>() {
     Err(_e) => Err("Failed to parse input as an int!".to_string()),
-    Ok(idx) if !(0..5).contains(&idx) => Err("Invalid card index! Please enter a number between 0 and 4".to_string()),
-    Ok(idx) if (0..5).contains(&idx) && hand.0[idx].0.is_none() => Err("You already played the card in that position! Select a different position".to_string()),
-    Ok(idx) if (0..5).contains(&idx) && hand.0[idx].0.is_some() => Ok(idx),
+    Ok(idx) if idx >= hand.0.len() => Err(format!("Invalid card index! Please enter a number between 0 and {}", hand.0.len() - 1)),
+    Ok(idx) if hand.0[idx].is_empty() => Err("You already played the card in that position! Select a different position".to_string()),
+    Ok(idx) if !playable_idxs.contains(&idx) && game.rules.contains(Rule::Order) => Err(format!("The Order rule is active! You must play the card in position {}", playable_idxs[0])),
+    Ok(idx) if !playable_idxs.contains(&idx) && game.rules.contains(Rule::Chaos) => Err(format!("Chaos selected the card in position {}! You must play that card", playable_idxs[0])),
+    Ok(idx) if !playable_idxs.contains(&idx) => Err("The rules don't allow that card to be played this turn! Select a different position".to_string()),
+    Ok(idx) if !hand.0[idx].is_empty() => Ok(idx),
     _ => Err("An undetermined parse error occurred. Please check your input and try again.".to_string())
   }
 }
@@ -22,10 +29,19 @@ pub fn valid_hand_idx(hand: &Hand, s:&str) -> Result<usize, String>{
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct GameCard
 { pub card: Card
+// The player currently controlling the card; changes on capture
 , pub player: Option<Player>
+// The player the card belongs to; only the post-game result cares about this, e.g. after a Swap
+, pub owner: Option<Player>
 }
 impl fmt::Display for GameCard {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.render(None))
+  }
+} 
+impl GameCard {
+  // The card's element sits bottom-left, and the element of the square it's on (if any) bottom-right
+  fn render(&self, square_element:Option<Element>) -> String {
     let tribe = self.card.stats.tribe.map(|t| t.to_string()).unwrap_or(" ".to_string());
     
     let color_line = |x:&String| match self.player {
@@ -36,13 +52,14 @@ impl fmt::Display for GameCard {
     let card_top = format!("{}{}{}", "╔".to_string().yellow(), color_line(&"━━━━━".to_string()), "╗".to_string().yellow());
     let top     = format!("{}{}{}", color_line(&format!("┃  {} ",format_number(self.card.stats.top))), tribe, color_line(&"┃".to_string()));
     let middle  = color_line(&format!("┃{} {} {}┃", format_number(self.card.stats.left), " ", format_number(self.card.stats.right)));
-    let bottom  = color_line(&format!("┃{} {} {}┃", " ", format_number(self.card.stats.bottom), " "));
+    let card_element = self.card.element.map(|e| e.to_string()).unwrap_or(" ".to_string());
+    let square_element = square_element.map(|e| e.to_string()).unwrap_or(" ".to_string());
+    let bottom  = format!("{}{}{}{}", color_line(&"┃".to_string()), card_element, color_line(&format!(" {} ", format_number(self.card.stats.bottom))), square_element) + &color_line(&"┃".to_string());
     let card_bottom = format!("{}{}{}", "╚".to_string().yellow(), color_line(&"━━━━━".to_string()), "╝".to_string().yellow());
     let out = vec![card_top, top, middle, bottom, card_bottom];
-    write!(f, "{}", out.join("\n"))
+    out.join("\n")
   }
-} 
-impl GameCard {
+
   pub fn flip(&self) -> GameCard {
     let mut out = self.clone();
     out.player = out.player.map(|x| match x { Player::Blue => Player::Red, Player::Red => Player::Blue});
@@ -52,10 +69,10 @@ impl GameCard {
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Move
-{ card: GameCard
-, coords: Coord
-, player: Player
-, is_combo: bool
+{ pub card: GameCard
+, pub coords: Coord
+, pub player: Player
+, pub is_combo: bool
 } impl Move {
   pub fn new(card:GameCard, coords:Coord, player:Player) -> Move {
     Move { card:card, coords:coords, player:player, is_combo:false }
@@ -78,7 +95,7 @@ impl fmt::Display for Player {
   }
 }
 impl Player {
-  fn other(&self) -> Player {
+  pub fn other(&self) -> Player {
     match self {
       Player::Red => Player::Blue,
       Player::Blue => Player::Red
@@ -86,7 +103,7 @@ impl Player {
   }
 }
 
-fn format_number(number:usize) -> String {
+pub fn format_number(number:usize) -> String {
   match number {
     1..=9 => number.to_string().bold().to_string(),
     10 => "A".bold().to_string(),
@@ -94,181 +111,299 @@ fn format_number(number:usize) -> String {
   }
 }
 
+// Inverse of format_number; accepts "A" as well as "10" for an Ace
+pub fn parse_number(s:&str) -> Option<usize> {
+  match s.trim() {
+    "A" | "a" => Some(10),
+    s => s.parse::<usize>().ok().filter(|x| (1..=10).contains(x))
+  }
+}
+
 #[allow(dead_code)]
+// A board square holds a card, and under Elemental may also carry an element
 #[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
-pub struct Square(pub Option<GameCard>);
+pub struct Square(pub Option<GameCard>, pub Option<Element>);
 impl fmt::Display for Square {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let out = match self {
-      Square(None) => String::from(vec!(
+      Square(None, None) => String::from(vec!(
                                     "╔─────╗"
                                   , "┃     ┃"
                                   , "┃     ┃"
                                   , "┃     ┃"
                                   , "╚─────╝"
                                   ).join("\n")),
-      Square(Some(card)) => format!("{}", card) 
+      Square(None, Some(element)) => [
+                                    "╔─────╗".to_string()
+                                  , "┃     ┃".to_string()
+                                  , format!("┃  {}  ┃", element)
+                                  , "┃     ┃".to_string()
+                                  , "╚─────╝".to_string()
+                                  ].join("\n"),
+      Square(Some(card), element) => card.render(*element)
     };
     write!(f, "{}", out)
   }
 }
 impl Square {
   pub fn new(card:GameCard) -> Self {
-    Self(Some(card))
+    Self(Some(card), None)
   }
   pub fn flip(self) -> Square {
-    let Square(sq) = self;
-    Square(sq.map(|card| card.clone().flip()))
+    let Square(sq, element) = self;
+    Square(sq.map(|card| card.clone().flip()), element)
   }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-pub struct Hand(pub [Square; 5]);
-impl fmt::Display for Hand {
+// A position in a hand; with closed or Three Open hands, the opponent's cards may not be known
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum HandSlot
+{ #[default] Empty
+, Known(GameCard)
+, Unknown
+// The card is hidden, but is known to be one of these
+, Candidates(Vec<GameCard>)
+}
+impl fmt::Display for HandSlot {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let hand_top =    "┌─────────┬─────────┬─────────┐";
-    let middle_sep =  "└────┬────┴────┬────┴────┬────┘";
-    let hand_bottom = "     └─────────┴─────────┘     ";
-
-    let fmt_top_row = || {
-      let row = vec![&self.0[0], &self.0[1], &self.0[2]];
-      let splitrow = 
-        row
-        .iter()
-        .map(|x| 
-          format!("{}", x)
-          .split("\n")
-          .map(|x| x.to_string())
-          .collect())
-        .collect::<Vec<Vec<String>>>();
-  
-      let n_lines = splitrow.first().unwrap().len();
-      let mut row_iters : Vec<_> = splitrow.into_iter().map(Vec::into_iter).collect();
-      let out_row = (0..n_lines)
-        .map(|_| row_iters.iter_mut().map(|x| x.next().unwrap()).collect())
-        .collect::<Vec<Vec<String>>>()
-        .into_iter()
-        .enumerate()
-        .map(|(idx, lines)| {
-          if idx != 0 {
-            format!("│ {} │", lines.join(" │ ")).to_string()
-          } else {
-            let interspersed_seps = (1..lines.len()).map(|n| format!(" │{}", n).to_string()).collect::<Vec<String>>();
-            let final_line = 
-              interleave(lines, interspersed_seps.into_iter())
-              .reduce(|string:String, next:String| format!("{}{}", string, next))
-              .unwrap();
-            format!("│{}{} │", 0, final_line).to_string()
-          }})
-        .collect::<Vec<String>>()
-        .join("\n")
-        ;
-      out_row
-    };
-
-    let fmt_bottom_row = || {
-      let row = vec![&self.0[3], &self.0[4]];
-      let splitrow = 
-        row
-        .iter()
-        .map(|x| 
-          format!("{}", x)
-          .split("\n")
-          .map(|x| x.to_string())
-          .collect())
-        .collect::<Vec<Vec<String>>>();
-  
-      let n_lines = splitrow.first().unwrap().len();
-      let mut row_iters : Vec<_> = splitrow.into_iter().map(Vec::into_iter).collect();
-      let out_row = (0..n_lines)
-        .map(|_| row_iters.iter_mut().map(|x| x.next().unwrap()).collect())
-        .collect::<Vec<Vec<String>>>()
-        .into_iter()
-        
-        .enumerate()
-        .map(|(idx, lines)| {
-          if idx != 0 {
-            format!("     │ {} │     ", lines.join(" │ ")).to_string()
-          } else {
-            let interspersed_seps = vec![format!(" │{}", 4).to_string()];
-            let final_line = 
-              interleave(lines, interspersed_seps.into_iter())
-              .reduce(|string:String, next:String| format!("{}{}", string, next))
-              .unwrap();
-            format!("     │{}{} │     ", 3, final_line).to_string()
-          }})
-        .collect::<Vec<String>>()
-        .join("\n")
-        ;
-      out_row
+    let hidden_card = |footer:String| [
+                          "╔─────╗".to_string()
+                        , "┃     ┃".to_string()
+                        , "┃  ?  ┃".to_string()
+                        , format!("┃{:^5}┃", footer)
+                        , "╚─────╝".to_string()
+                        ].join("\n");
+    let out = match self {
+      HandSlot::Empty => format!("{}", Square(None, None)),
+      HandSlot::Known(card) => format!("{}", card),
+      HandSlot::Unknown => hidden_card("".to_string()),
+      HandSlot::Candidates(cards) => hidden_card(format!("{}", cards.len().min(999)))
     };
-    
-  let mut top_row: Vec<String> = vec![fmt_top_row()];
-  let mut bottom_row = vec![fmt_bottom_row()];
-  let rows = 
-    &mut vec![hand_top.to_string()];
-    rows.append(&mut top_row);
-    rows.append(&mut vec![middle_sep.to_string()]);
-    rows.append(&mut bottom_row);
-    rows.append(&mut vec![hand_bottom.to_string()]);
-
-    let out = rows.join("\n");
     write!(f, "{}", out)
   }
 }
+impl HandSlot {
+  pub fn card(&self) -> Option<&GameCard> {
+    match self {
+      HandSlot::Known(card) => Some(card),
+      _ => None
+    }
+  }
+  pub fn is_empty(&self) -> bool {
+    matches!(self, HandSlot::Empty)
+  }
+  pub fn is_hidden(&self) -> bool {
+    matches!(self, HandSlot::Unknown | HandSlot::Candidates(_))
+  }
+}
 
-#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
-pub struct Board(pub [[Square;3];3]);
-impl fmt::Display for Board {
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Hand(pub Vec<HandSlot>);
+impl Hand {
+  pub fn new(size:usize) -> Hand {
+    Hand(vec![HandSlot::Empty; size])
+  }
+  // Index of the leftmost card still in hand; the only playable card under Order
+  pub fn first_card_idx(&self) -> Option<usize> {
+    self.0.iter().position(|slot| !slot.is_empty())
+  }
+  pub fn occupied_idxs(&self) -> Vec<usize> {
+    self.0.iter().enumerate().filter_map(|(idx, slot)| (!slot.is_empty()).then_some(idx)).collect()
+  }
+}
+
+// Draws the horizontal line between two rows of 9-wide cells; `lower_indent` shifts the lower row's cells right.
+// Either row may have no cells, giving the top or bottom border.
+fn cell_separator(upper_cells:usize, lower_cells:usize, lower_indent:usize) -> String {
+  let upper_span = (upper_cells > 0).then_some((0, 10 * upper_cells));
+  let lower_span = (lower_cells > 0).then_some((lower_indent, lower_indent + 10 * lower_cells));
+  let covers = |span:Option<(usize, usize)>, pos:usize| span.is_some_and(|(start, end)| (start..=end).contains(&pos));
+  let is_edge = |span:Option<(usize, usize)>, pos:usize| covers(span, pos) && (pos - span.unwrap().0).is_multiple_of(10);
+  let width = upper_span.map_or(0, |(_, end)| end).max(lower_span.map_or(0, |(_, end)| end)) + 1;
+
+  (0..width)
+    .map(|pos| {
+      match (is_edge(upper_span, pos), is_edge(lower_span, pos)) {
+        (true, true) if pos == 0 => '├',
+        (true, true) if pos == width - 1 => '┤',
+        (true, true) => '┼',
+        (true, false) if covers(lower_span, pos) => '┴',
+        (true, false) if pos == 0 => '└',
+        (true, false) if pos == 10 * upper_cells => '┘',
+        (true, false) => '┴',
+        (false, true) if covers(upper_span, pos) => '┬',
+        (false, true) if pos == lower_indent => '┌',
+        (false, true) if pos == lower_indent + 10 * lower_cells => '┐',
+        (false, true) => '┬',
+        (false, false) if covers(upper_span, pos) || covers(lower_span, pos) => '─',
+        (false, false) => ' '
+      }
+    })
+    .collect()
+}
+
+// Lays out a row of cells side by side. `labels`, if given, numbers the cells with hand indices from a first
+// index, padded to a width of at most 2; the cells' contents shift right to make room.
+fn fmt_cells(cells:Vec<String>, labels:Option<(usize, usize)>) -> String {
+  let splitrow = 
+    cells
+    .iter()
+    .map(|x| x.split("\n").map(|x| x.to_string()).collect())
+    .collect::<Vec<Vec<String>>>();
+
+  let n_lines = splitrow.first().map_or(0, Vec::len);
+  let mut row_iters : Vec<_> = splitrow.into_iter().map(Vec::into_iter).collect();
+  (0..n_lines)
+    .map(|_| row_iters.iter_mut().map(|x| x.next().unwrap()).collect())
+    .collect::<Vec<Vec<String>>>()
+    .into_iter()
+    .enumerate()
+    .map(|(line_idx, lines)| {
+      let label_width = labels.map_or(1, |(_, width)| width);
+      let cells = lines.into_iter().enumerate().map(|(idx, line)| {
+        let label = match labels {
+          Some((first_idx, width)) if line_idx == 0 => format!("{:<width$}", first_idx + idx),
+          _ => " ".repeat(label_width)
+        };
+        format!("│{}{}{}", label, line, " ".repeat(2 - label_width))
+      });
+      format!("{}│", cells.collect::<String>())
+    })
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+impl fmt::Display for Hand {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let board_top =    "┌─────────┬─────────┬─────────┐";
-    let row_sep =      "├─────────┼─────────┼─────────┤";
-    let board_bottom = "└─────────┴─────────┴─────────┘";
+    // Two rows; with an odd number of cards, the shorter bottom row is centred
+    let top_cells = self.0.len().div_ceil(2);
+    let bottom_cells = self.0.len() / 2;
+    let indent = 5 * (top_cells - bottom_cells);
+    let padding = " ".repeat(indent);
+
+    let label_width = self.0.len().saturating_sub(1).to_string().len();
+    let fmt_row = |slots:&[HandSlot], first_idx:usize| fmt_cells(slots.iter().map(|slot| format!("{}", slot)).collect(), Some((first_idx, label_width)));
+    let bottom_row = 
+      fmt_row(&self.0[top_cells..], top_cells)
+      .split("\n")
+      .map(|line| format!("{}{}{}", padding, line, padding))
+      .join("\n");
 
-    let Board(board) = &self;
+    let rows =
+      [ cell_separator(0, top_cells, 0)
+      , fmt_row(&self.0[..top_cells], 0)
+      , cell_separator(top_cells, bottom_cells, indent)
+      , bottom_row
+      , format!("{}{}{}", padding, cell_separator(bottom_cells, 0, 0), padding)
+      ];
 
-    let fmt_row = |n:usize| {
-      let row = vec![&board[n][0], &board[n][1], &board[n][2]];
-      let splitrow = 
-        row
-        .iter()
-        .map(|x| 
-          format!("{}", x)
-          .split("\n")
-          .map(|x| x.to_string())
-          .collect())
-        .collect::<Vec<Vec<String>>>();
-  
-      let n_lines = splitrow.first().unwrap().len();
-      let mut row_iters : Vec<_> = splitrow.into_iter().map(Vec::into_iter).collect();
-      let out_row = (0..n_lines)
-        .map(|_| row_iters.iter_mut().map(|x| x.next().unwrap()).collect())
-        .collect::<Vec<Vec<String>>>()
-        .iter()
-        .map(|x| format!("│ {} │", x.join(" │ ")))
-        .collect::<Vec<String>>()
-        .join("\n")
-        ;
-      out_row
-    };
+    write!(f, "{}", rows.join("\n"))
+  }
+}
 
-    let mut rows = 
-      (0..=2)
-      .into_iter()
-      .map(fmt_row)
-      .intersperse(row_sep.to_string())
-      .collect::<Vec<String>>();
-   
-    let full_board = &mut vec![board_top.to_string()];
-    full_board.append(&mut rows);
-    full_board.append(&mut vec![board_bottom.to_string()]);
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Board
+{ pub squares: Vec<Vec<Square>>
+// Number of cards of each Tribe placed on the board, indexed by Tribe::idx; drives Ascension and Descension
+, pub tribe_counts: [usize; 4]
+}
+impl Default for Board {
+  fn default() -> Self {
+    Board::new(3, 3)
+  }
+}
+impl Board {
+  pub fn new(rows:usize, cols:usize) -> Board {
+    Board { squares: vec![vec![Square::default(); cols]; rows], tribe_counts: [0; 4] }
+  }
+  pub fn rows(&self) -> usize {
+    self.squares.len()
+  }
+  pub fn cols(&self) -> usize {
+    self.squares.first().map_or(0, Vec::len)
+  }
+  // Each player needs enough cards to fill half the board, with the first player's extra card left over
+  pub fn hand_size(&self) -> usize {
+    (self.rows() * self.cols()).div_ceil(2)
+  }
+  // The position at the given (possibly out of range) indices, if it is on the board
+  pub fn coord(&self, row:isize, col:isize) -> Option<Coord> {
+    let on_board = (0..self.rows() as isize).contains(&row) && (0..self.cols() as isize).contains(&col);
+    on_board.then(|| Coord::new(row as usize, col as usize))
+  }
+  pub fn contains(&self, coords:&Coord) -> bool {
+    coords.row < self.rows() && coords.col < self.cols()
+  }
+  pub fn coords(&self) -> impl Iterator<Item=Coord> {
+    (0..self.rows()).cartesian_product(0..self.cols()).map(|(row, col)| Coord::new(row, col))
+  }
+  pub fn square(&self, coords:&Coord) -> &Square {
+    &self.squares[coords.row][coords.col]
+  }
+  pub fn square_mut(&mut self, coords:&Coord) -> &mut Square {
+    &mut self.squares[coords.row][coords.col]
+  }
+}
+
+// One of the board's rotations and reflections: flip the rows, then the columns, then swap rows with columns.
+// Only square boards can be transposed, so they have 8 symmetries and other boards 4.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Symmetry
+{ pub flip_rows: bool
+, pub flip_cols: bool
+, pub transpose: bool
+}
+impl Symmetry {
+  pub fn all(board:&Board) -> impl Iterator<Item=Symmetry> {
+    let transposes = if board.rows() == board.cols() { &[false, true][..] } else { &[false][..] };
+    transposes.iter()
+      .cartesian_product([false, true].iter().cartesian_product([false, true]))
+      .map(|(transpose, (flip_rows, flip_cols))| Symmetry { flip_rows: *flip_rows, flip_cols, transpose: *transpose })
+  }
+  // Where the square at `coords` ends up
+  pub fn apply(self, board:&Board, coords:&Coord) -> Coord {
+    let row = if self.flip_rows { board.rows() - 1 - coords.row } else { coords.row };
+    let col = if self.flip_cols { board.cols() - 1 - coords.col } else { coords.col };
+    if self.transpose { Coord::new(col, row) } else { Coord::new(row, col) }
+  }
+  // The square that ends up at `coords`
+  pub fn invert(self, board:&Board, coords:&Coord) -> Coord {
+    let (row, col) = if self.transpose { (coords.col, coords.row) } else { (coords.row, coords.col) };
+    let row = if self.flip_rows { board.rows() - 1 - row } else { row };
+    let col = if self.flip_cols { board.cols() - 1 - col } else { col };
+    Coord::new(row, col)
+  }
+  // A card's sides once the board is transformed, so each value still faces the same neighbour
+  pub fn apply_to_stats(self, stats:&CardStats) -> CardStats {
+    let (top, bottom) = if self.flip_rows { (stats.bottom, stats.top) } else { (stats.top, stats.bottom) };
+    let (left, right) = if self.flip_cols { (stats.right, stats.left) } else { (stats.left, stats.right) };
+    match self.transpose {
+      true => CardStats { top: left, right: bottom, bottom: right, left: top, tribe: stats.tribe },
+      false => CardStats { top, right, bottom, left, tribe: stats.tribe }
+    }
+  }
+}
 
-    let out_rows = full_board.join("\n");
+impl fmt::Display for Board {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let cols = self.cols();
+    let rows = 
+      self.squares
+      .iter()
+      .map(|row| fmt_cells(row.iter().map(|square| format!("{}", square)).collect(), None))
+      .intersperse(cell_separator(cols, cols, 0));
+
+    let full_board = 
+      std::iter::once(cell_separator(0, cols, 0))
+      .chain(rows)
+      .chain(std::iter::once(cell_separator(cols, 0, 0)))
+      .collect::<Vec<String>>();
 
-    write!(f, "{}", out_rows)
+    write!(f, "{}", full_board.join("\n"))
   }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum GameResult
 { Draw
 , Win(Player)
@@ -279,10 +414,32 @@ pub enum MoveResult
 { Finished(GameResult)
 , Combo(Vec<Move>)
 , NextMove
+// The round was drawn under Sudden Death, and a new round has started
+, NextRound
+}
+
+// Fixed-width and little-endian, unlike `Hash` for usize, so keys kept on disk don't depend on the platform
+fn write_u32(bytes:&mut Vec<u8>, value:usize) {
+  bytes.extend((value as u32).to_le_bytes());
+}
+
+fn element_tag(element:Option<Element>) -> u8 {
+  element.map_or(0, |element| element as u8 + 1)
 }
 
+// A card's sides, turned by `symmetry`, its tribe and its element, written out byte by byte
+pub fn write_card_face(card:&Card, symmetry:Symmetry, bytes:&mut Vec<u8>) {
+  let stats = symmetry.apply_to_stats(&card.stats);
+  bytes.extend([stats.top, stats.right, stats.bottom, stats.left].map(|side| side as u8));
+  bytes.push(stats.tribe.map_or(0, |tribe| tribe.idx() as u8 + 1));
+  bytes.push(element_tag(card.element));
+}
+
+// A neighbouring card, where it is, and which of its sides faces the card just placed
+pub type Comparison = (Coord, GameCard, Side);
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum Side 
+pub enum Side 
 { Top
 , Right
 , Bottom
@@ -290,30 +447,198 @@ enum Side
 }
 
 #[allow(dead_code)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum Rule
 { AllOpen
-//TODO , ThreeOpen
-//TODO , Chaos
-//TODO , Order
+, ThreeOpen
+, Chaos
+, Order
 , Plus
-//TODO, Same
-//TODO, Reverse
-//TODO, Ascension
-//TODO, Descension
-//TODO, FallenAce
+, Same
+, Reverse
+, Ascension
+, Descension
+, FallenAce
+, Swap
+, SuddenDeath
+, Random
+// Hands are picked from a shared pool before the game; see draft.rs
+, Draft
+// FF8-style: some board squares carry an element that boosts matching cards and weakens the rest
+, Elemental
+// Board edges count as A-valued neighbours for Same (Same Wall) or Plus (Plus Wall), alongside the base rule
+, SameWall
+, PlusWall
+// Resolved into concrete rules from a pool when the game starts
+, Roulette
+}
+impl FromStr for Rule {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let normalized = s.trim().to_lowercase().replace([' ', '_', '-'], "");
+    match normalized.as_str() {
+      "allopen" => Ok(Rule::AllOpen),
+      "threeopen" => Ok(Rule::ThreeOpen),
+      "chaos" => Ok(Rule::Chaos),
+      "order" => Ok(Rule::Order),
+      "plus" => Ok(Rule::Plus),
+      "same" => Ok(Rule::Same),
+      "reverse" => Ok(Rule::Reverse),
+      "ascension" => Ok(Rule::Ascension),
+      "descension" => Ok(Rule::Descension),
+      "fallenace" => Ok(Rule::FallenAce),
+      "swap" => Ok(Rule::Swap),
+      "suddendeath" => Ok(Rule::SuddenDeath),
+      "random" => Ok(Rule::Random),
+      "draft" => Ok(Rule::Draft),
+      "elemental" => Ok(Rule::Elemental),
+      "samewall" => Ok(Rule::SameWall),
+      "pluswall" => Ok(Rule::PlusWall),
+      "roulette" => Ok(Rule::Roulette),
+      _ => Err(format!("Unknown rule \"{}\"", s.trim()))
+    }
+  }
+}
+
+
+
+impl Rule {
+  pub const ALL: [Rule; 18] = 
+    [ Rule::AllOpen, Rule::ThreeOpen, Rule::Chaos, Rule::Order, Rule::Plus, Rule::Same, Rule::Reverse
+    , Rule::Ascension, Rule::Descension, Rule::FallenAce, Rule::Swap, Rule::SuddenDeath, Rule::Random
+    , Rule::Draft, Rule::Elemental, Rule::SameWall, Rule::PlusWall, Rule::Roulette
+    ];
+
+  fn bit(self) -> u32 { 1 << self as u32 }
 }
 
+// Pairs of rules that can't be in play together
+const CONFLICTING_RULES: [(Rule, Rule); 4] = 
+  [ (Rule::Ascension, Rule::Descension)
+  , (Rule::Order, Rule::Chaos)
+  , (Rule::AllOpen, Rule::ThreeOpen)
+  , (Rule::Random, Rule::Draft)
+  ];
+
+// Rules that only modify another rule, paired with the rule they need
+const DEPENDENT_RULES: [(Rule, Rule); 2] = 
+  [ (Rule::SameWall, Rule::Same)
+  , (Rule::PlusWall, Rule::Plus)
+  ];
+
+// The rules in play, as a bitset so membership checks in the capture logic and the search stay cheap
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct RuleSet(u32);
+impl RuleSet {
+  pub fn new() -> RuleSet {
+    RuleSet(0)
+  }
+  pub fn contains(&self, rule:Rule) -> bool {
+    self.0 & rule.bit() != 0
+  }
+  pub fn with(self, rule:Rule) -> RuleSet {
+    RuleSet(self.0 | rule.bit())
+  }
+  pub fn without(self, rule:Rule) -> RuleSet {
+    RuleSet(self.0 & !rule.bit())
+  }
+  pub fn is_empty(&self) -> bool {
+    self.0 == 0
+  }
+  pub fn iter(&self) -> impl Iterator<Item=Rule> + '_ {
+    Rule::ALL.into_iter().filter(|rule| self.contains(*rule))
+  }
+
+  pub fn conflicts(&self) -> Vec<(Rule, Rule)> {
+    CONFLICTING_RULES.into_iter().filter(|(a, b)| self.contains(*a) && self.contains(*b)).collect()
+  }
 
+  // Rules in play without the rule they modify
+  pub fn missing_requirements(&self) -> Vec<(Rule, Rule)> {
+    DEPENDENT_RULES.into_iter().filter(|(rule, required)| self.contains(*rule) && !self.contains(*required)).collect()
+  }
 
-fn format_score(score:isize) -> String {
+  // Lists every conflicting pair and missing requirement, not just the first
+  pub fn validate(self) -> Result<RuleSet, String> {
+    let conflicts = match self.conflicts() {
+      conflicts if conflicts.is_empty() => None,
+      conflicts => Some(format!("Conflicting rules: {}", conflicts.iter().map(|(a, b)| format!("{:?} and {:?}", a, b)).join(", ")))
+    };
+    let missing = self.missing_requirements().into_iter().map(|(rule, required)| Some(format!("{:?} needs {:?}", rule, required)));
+    match std::iter::once(conflicts).chain(missing).flatten().collect::<Vec<String>>() {
+      errors if errors.is_empty() => Ok(self),
+      errors => Err(errors.join("; "))
+    }
+  }
+}
+impl FromIterator<Rule> for RuleSet {
+  fn from_iter<I: IntoIterator<Item=Rule>>(rules: I) -> Self {
+    rules.into_iter().fold(RuleSet::new(), RuleSet::with)
+  }
+}
+// Parses a comma separated list such as "plus,same,order", rejecting conflicting rules
+impl FromStr for RuleSet {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    s
+    .split(',')
+    .filter(|x| !x.trim().is_empty())
+    .map(|x| x.parse::<Rule>())
+    .collect::<Result<RuleSet, String>>()?
+    .validate()
+  }
+}
+impl fmt::Display for RuleSet {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.iter().map(|rule| format!("{:?}", rule)).join(", "))
+  }
+}
+
+
+
+// The rules Roulette can roll, unless a game is configured with its own pool
+pub const ROULETTE_POOL: [Rule; 13] = 
+  [ Rule::AllOpen, Rule::ThreeOpen, Rule::Chaos, Rule::Order, Rule::Plus, Rule::Same, Rule::Reverse
+  , Rule::Ascension, Rule::Descension, Rule::FallenAce, Rule::Swap, Rule::SuddenDeath, Rule::Random
+  ];
+
+// How many rules a Roulette rolls
+pub const ROULETTE_PICKS: usize = 1;
+
+// Replaces Roulette with `picks` rules from the pool that aren't already in play and don't conflict with it
+pub fn resolve_roulette<R: Rng>(rules:RuleSet, rng:&mut R, pool:&[Rule], picks:usize) -> RuleSet {
+  if !rules.contains(Rule::Roulette) { return rules }
+  let mut resolved = rules.without(Rule::Roulette);
+  for _ in 0..picks {
+    let candidates = 
+      pool.iter().copied()
+      .filter(|rule| *rule != Rule::Roulette && !resolved.contains(*rule) && resolved.with(*rule).validate().is_ok())
+      .collect::<Vec<Rule>>();
+    if let Some(rule) = rng.choose(&candidates) {
+      resolved = resolved.with(*rule);
+    }
+  }
+  resolved
+}
+
+// Chance of each square carrying an element under Elemental
+const ELEMENTAL_SQUARE_CHANCE: f32 = 0.25;
+
+
+
+// Blue's score out of 2 * hand_size cards, centred in `width` columns
+fn format_score(score:isize, hand_size:usize, width:usize) -> String {
+  let hand_size = hand_size as isize;
+  let bar_width = 4 * hand_size as usize + 1;
+  let left_padding = " ".repeat(width.saturating_sub(bar_width) / 2);
+  let right_padding = " ".repeat(width.saturating_sub(bar_width + left_padding.len()));
   let out = match score {
-    score if (-4..=4).contains(&score) => {
-      let blue_score = "[]".to_string().repeat((5 + score).try_into().unwrap()).blue().to_string();
-      let red_score = "[]".to_string().repeat((5 - score).try_into().unwrap()).red().to_string();
-      format!("     {}║{}     ", blue_score, red_score)
+    score if (-hand_size..=hand_size).contains(&score) => {
+      let blue_score = "[]".to_string().repeat((hand_size + score).try_into().unwrap()).blue().to_string();
+      let red_score = "[]".to_string().repeat((hand_size - score).try_into().unwrap()).red().to_string();
+      format!("{}{}║{}{}", left_padding, blue_score, red_score, right_padding)
     },
-    _ => "   ##########ERROR##########   ".to_string()
+    _ => format!("{}{:#^width$}{}", left_padding, "ERROR", right_padding, width = bar_width)
   };
   out
 }
@@ -328,93 +653,194 @@ pub struct Game
 , pub first_player : Player
 , pub my_color : Player
 //, pub score_blue: Score
-, pub rules: Vec<Rule>
+, pub rules: RuleSet
+// Hand index of the card Chaos selected for the current turn
+, pub forced_card: Option<usize>
+// Starts at 1; Sudden Death adds a round for each draw
+, pub round: usize
+// Set when cards change hands afterwards, so the AI weighs which of its cards it risks
+, pub trade_rule: Option<TradeRule>
+// Custom rules played on top of `rules`; see rules.rs
+, pub house_rules: HouseRules
 } 
 impl fmt::Display for Game {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let mut blue_hand_str = format!("{}",self.hands[&Player::Blue]).split("\n").map(|x| x.to_string()).collect::<Vec<String>>();
-    let mut red_hand_str = format!("{}", self.hands[&Player::Red]).split("\n").map(|x| x.to_string()).collect::<Vec<String>>();
-    let board_str = format!("{}", self.board.clone()).split("\n").map(|x| x.to_string()).collect::<Vec<String>>();
-    let vertical_padding = board_str.len() - blue_hand_str.len();
-    let upper_padding = vertical_padding / 2;
-    let lower_padding = vertical_padding / 2 + vertical_padding % 2;
-    let hand_padding = (0..31).map(|_| " ").collect::<String>();
-    let mut blue_hand_for_print = (0..upper_padding).map(|_| format!("{}", hand_padding)).collect::<Vec<String>>();
-    blue_hand_for_print.append(&mut blue_hand_str);
-    blue_hand_for_print.append(&mut vec![hand_padding.clone(); lower_padding]);
-
-    let mut red_hand_for_print = (0..upper_padding).map(|_| format!("{}", hand_padding)).collect::<Vec<String>>();
-    red_hand_for_print.append(&mut red_hand_str);
-    red_hand_for_print.append(&mut vec![hand_padding.clone(); lower_padding]);
+    // Render cards with any Ascension, Descension or Elemental modifiers applied
+    let effective_card = |card:&GameCard, coords:Option<&Coord>| GameCard { card: Card { stats: self.effective_stats(card, coords), ..card.card.clone() }, ..card.clone() };
+    let effective_hand = |player:Player| Hand(self.hands[&player].0.iter().cloned().map(|slot| match slot { HandSlot::Known(card) => HandSlot::Known(effective_card(&card, None)), slot => slot }).collect());
+    let mut effective_board = self.board.clone();
+    for coords in self.board.coords() {
+      effective_board.square_mut(&coords).0 = self.board.square(&coords).0.as_ref().map(|card| effective_card(card, Some(&coords)));
+    }
+
+    let blue_hand_str = format!("{}", effective_hand(Player::Blue)).split("\n").map(|x| x.to_string()).collect::<Vec<String>>();
+    let red_hand_str = format!("{}", effective_hand(Player::Red)).split("\n").map(|x| x.to_string()).collect::<Vec<String>>();
+    let board_str = format!("{}", effective_board).split("\n").map(|x| x.to_string()).collect::<Vec<String>>();
+    let hand_padding = " ".repeat(blue_hand_str.first().map_or(0, |line| line.chars().count()));
+    let board_padding = " ".repeat(board_str.first().map_or(0, |line| line.chars().count()));
+
+    // Centre the shorter of the hands and the board vertically against the other
+    let n_lines = board_str.len().max(blue_hand_str.len());
+    let pad_lines = |lines:Vec<String>, padding:&String| {
+      let upper_padding = (n_lines - lines.len()) / 2;
+      let lower_padding = n_lines - lines.len() - upper_padding;
+      vec![padding.clone(); upper_padding].into_iter().chain(lines).chain(vec![padding.clone(); lower_padding]).collect::<Vec<String>>()
+    };
 
     let out = 
-      izip!(blue_hand_for_print, board_str, red_hand_for_print)
+      izip!(pad_lines(blue_hand_str, &hand_padding), pad_lines(board_str, &board_padding), pad_lines(red_hand_str, &hand_padding))
       .map(|(bhand, board, rhand)| format!("{}   {}   {}", bhand, board, rhand).to_string())
       .join("\n");
 
     let score = self.get_score(&Player::Blue);
 
-    write!(f, "\n{0}   {1}   {0}\n{2}\n", hand_padding, format_score(score), out)
+    write!(f, "\n{0}   {1}   {0}\n{2}\n", hand_padding, format_score(score, self.hand_size(), board_padding.len()), out)
   }
 }
 impl Game {
-  fn open_squares(&self) -> Vec<Coord> {
-    let all_indices = (0..3).cartesian_product(0..3);
-    all_indices
-    .filter(|(row, col)| self.board.0[*row as usize][*col as usize].0.is_none())
-    .map(|(row, col)| Coord{row:Row::from_idx(row).unwrap(), col:Column::from_idx(col).unwrap()}) 
+  pub fn new(first_player:Player, my_color:Player, rules:RuleSet) -> Game {
+    Game::with_board(Board::default(), first_player, my_color, rules)
+  }
+
+  pub fn with_board(board:Board, first_player:Player, my_color:Player, rules:RuleSet) -> Game {
+    let empty_hand = Hand::new(board.hand_size());
+    Game
+      { turn: first_player
+      , hands: HashMap::from(
+        [ (Player::Red, empty_hand.clone()),
+          (Player::Blue, empty_hand)
+        ]
+      )
+      , board
+      , first_player
+      , my_color
+      , rules
+      , forced_card: None
+      , round: 1
+      , trade_rule: None
+      , house_rules: HouseRules::default()
+      }
+  }
+
+  pub fn open_squares(&self) -> Vec<Coord> {
+    self.board.coords()
+    .filter(|coords| self.board.square(coords).0.is_none())
     .collect::<Vec<Coord>>()
   }
 
+  pub fn hand_size(&self) -> usize {
+    self.board.hand_size()
+  }
+
 
 
   pub fn get_score(&self, player:&Player) -> isize {
-    let cards_in_hand = self.hands[player].0.iter().filter(|x| (*x).0.is_some()).collect::<Vec<&Square>>().len();
-    let cards_on_board = self.board.0.iter().flatten().filter_map(|x| x.clone().0).filter(|x| x.player == Some(*player)).collect::<Vec<GameCard>>().len();
-    (cards_in_hand as isize + cards_on_board as isize) - 5
+    let cards_in_hand = self.hands[player].occupied_idxs().len();
+    let cards_on_board = self.board.squares.iter().flatten().filter_map(|x| x.clone().0).filter(|x| x.player == Some(*player)).collect::<Vec<GameCard>>().len();
+    (cards_in_hand as isize + cards_on_board as isize) - self.hand_size() as isize
+  }
+
+
+
+  // The built-in rules in play, followed by any house rules
+  pub fn active_rules(&self) -> impl Iterator<Item=&(dyn GameRule + 'static)> + '_ {
+    built_in_rules(self.rules).chain(self.house_rules.iter())
+  }
+
+
+
+  // Hand indices the current player may play from; restricted by Order, or by the card Chaos selected
+  pub fn playable_card_idxs(&self) -> Vec<usize> {
+    self.hands[&self.turn]
+      .occupied_idxs()
+      .into_iter()
+      .filter(|idx| self.active_rules().all(|rule| rule.allows_card(self, *idx)))
+      .collect()
+  }
+
+
+
+  // Under Chaos, each turn starts with the game selecting a card before the player can move
+  pub fn awaiting_chaos(&self) -> bool {
+    self.rules.contains(Rule::Chaos) 
+      && self.forced_card.is_none() 
+      && !self.open_squares().is_empty() 
+      && self.hands[&self.turn].first_card_idx().is_some()
+  }
+
+
+
+  #[allow(dead_code)]
+  pub fn force_card(&mut self, idx:usize) -> Option<&mut Game> {
+    match self.hands[&self.turn].0.get(idx) {
+      Some(slot) if !slot.is_empty() => { self.forced_card = Some(idx); Some(self) }
+      _ => None
+    }
+  }
+
+
+
+  // Simulates Chaos selecting a card from the current player's hand
+  pub fn roll_forced_card<R: Rng>(&mut self, rng:&mut R) -> Option<usize> {
+    let candidates = self.hands[&self.turn].occupied_idxs();
+    let idx = rng.choose(&candidates).copied();
+    self.forced_card = idx;
+    idx
   }
 
 
 
   fn is_valid_move(&self, user_move:&Move) -> bool {
     let is_players_turn = user_move.player == self.turn;
-    let is_available_card = self.hands[&self.turn].0.contains(&Square::new(user_move.card.clone()));
-    let is_available_space = self.board.0[user_move.coords.row.idx()][user_move.coords.col.idx()].0.is_none(); 
+    let hand = &self.hands[&self.turn];
+    let is_available_card = self.playable_card_idxs().into_iter().any(|idx| hand.0[idx].card() == Some(&user_move.card));
+    let is_available_space = self.board.contains(&user_move.coords) && self.board.square(&user_move.coords).0.is_none(); 
+    let is_allowed = self.active_rules().all(|rule| rule.allows_move(self, user_move));
 
-    (is_players_turn && is_available_card && is_available_space) || user_move.is_combo
+    (is_players_turn && is_available_card && is_available_space && is_allowed) || user_move.is_combo
   }
 
 
 
   fn get_valid_moves(&self) -> Vec<Move> {
-    let cards = self.hands[&self.turn].0.iter().filter_map(|x| x.0.clone()).collect::<Vec<GameCard>>();
+    let hand = &self.hands[&self.turn];
+    // Hidden cards can't be played until they are revealed or determinized
+    let cards = self.playable_card_idxs().into_iter().filter_map(|idx| hand.0[idx].card().cloned()).collect::<Vec<GameCard>>();
     let spaces = self.open_squares();
-    let cartesian_product = itertools::iproduct!(cards, spaces).map(|(card, coord)| Move::new(card, coord, self.turn)).collect::<Vec<Move>>();
+    let cartesian_product = 
+      itertools::iproduct!(cards, spaces)
+      .map(|(card, coord)| Move::new(card, coord, self.turn))
+      .filter(|user_move| self.active_rules().all(|rule| rule.allows_move(self, user_move)))
+      .collect::<Vec<Move>>();
     cartesian_product
   }
 
 
 
   fn add_card_to_board(&mut self, user_move:&Move) -> () {
-    self.board.0[user_move.coords.row.idx()][user_move.coords.col.idx()] = Square::new(user_move.card.clone())
+    self.board.square_mut(&user_move.coords).0 = Some(user_move.card.clone())
   }
 
 
   #[allow(dead_code)]
   pub fn add_card_to_hand(&mut self, mut new_card:GameCard, player:Player) -> &mut Game {
     new_card.player = Some(player);
-  
+    new_card.owner = Some(player);
+    self.add_slot_to_hand(HandSlot::Known(new_card), player)
+  }
+
+
 
+  pub fn add_slot_to_hand(&mut self, slot:HandSlot, player:Player) -> &mut Game {
     let available_idx = 
     self.hands[&player].0
       .iter()
-      .enumerate()
-      .find_map(|(idx, ele)| if ele.0.is_none() {Some(idx)} else {None});
+      .position(|ele| ele.is_empty());
     
     match available_idx 
     {
       Option::Some(idx) => 
-      self.hands.get_mut(&player).unwrap().0[idx] = Square::new(new_card),
+      self.hands.get_mut(&player).unwrap().0[idx] = slot,
       Option::None => ()
     }
     self
@@ -422,16 +848,122 @@ impl Game {
 
 
 
+  // Replaces a hidden slot with the card the opponent actually played from it
+  pub fn reveal_card(&mut self, mut card:GameCard, player:Player, idx:usize) -> &mut Game {
+    card.player = Some(player);
+    card.owner = card.owner.or(Some(player));
+    self.hands.get_mut(&player).unwrap().0[idx] = HandSlot::Known(card);
+    self
+  }
+
+
+
+  // Exchanges a card between the hands; each card keeps its owner, but is now played by the other player
+  pub fn swap_cards(&mut self, blue_idx:usize, red_idx:usize) -> &mut Game {
+    let take_slot = |game:&mut Game, player:Player, idx:usize| std::mem::take(&mut game.hands.get_mut(&player).unwrap().0[idx]);
+    let blue_slot = take_slot(self, Player::Blue, blue_idx);
+    let red_slot = take_slot(self, Player::Red, red_idx);
+    let give_slot = |game:&mut Game, slot:HandSlot, player:Player, idx:usize| {
+      game.hands.get_mut(&player).unwrap().0[idx] = match slot {
+        HandSlot::Known(card) => HandSlot::Known(GameCard { player: Some(player), ..card }),
+        slot => slot
+      }
+    };
+    give_slot(self, red_slot, Player::Blue, blue_idx);
+    give_slot(self, blue_slot, Player::Red, red_idx);
+    self
+  }
+
+
+
+  // Simulates Swap: exchanges a random card from each hand, returning the (blue, red) positions swapped
+  pub fn random_swap<R: Rng>(&mut self, rng:&mut R) -> Option<(usize, usize)> {
+    let blue_idx = *rng.choose(&self.hands[&Player::Blue].occupied_idxs())?;
+    let red_idx = *rng.choose(&self.hands[&Player::Red].occupied_idxs())?;
+    self.swap_cards(blue_idx, red_idx);
+    Some((blue_idx, red_idx))
+  }
+
+
+
+  // Every card belonging to the given player, wherever it is and whoever controls it now
+  pub fn cards_owned_by(&self, owner:Player) -> Vec<GameCard> {
+    self.hands.values()
+      .flat_map(|hand| hand.0.iter().filter_map(HandSlot::card))
+      .chain(self.board.squares.iter().flatten().filter_map(|square| square.0.as_ref()))
+      .filter(|card| card.owner == Some(owner))
+      .cloned()
+      .collect()
+  }
+
+
+
+  // None if the square isn't on the board
+  pub fn set_square_element(&mut self, coords:&Coord, element:Option<Element>) -> Option<&mut Game> {
+    if !self.board.contains(coords) {
+      return None
+    }
+    self.board.square_mut(coords).1 = element;
+    Some(self)
+  }
+
+  // Elemental setup: each square independently may be given a random element
+  pub fn assign_random_elements<R: Rng>(&mut self, rng:&mut R) -> &mut Game {
+    for square in self.board.squares.iter_mut().flatten() {
+      square.1 = 
+        if rng.gen::<f32>() < ELEMENTAL_SQUARE_CHANCE { rng.choose(&Element::ALL).copied() }
+        else { None };
+    }
+    self
+  }
+
+
+
+  pub fn has_hidden_cards(&self) -> bool {
+    self.hands.values().any(|hand| hand.0.iter().any(HandSlot::is_hidden))
+  }
+
+
+
+  // Fills every hidden slot with a card sampled from its candidates (or from the pool, for Unknown slots),
+  // avoiding cards already known to be in play. The result is a fully known game the search can run on.
+  pub fn determinize<R: Rng>(&self, rng:&mut R, pool:&[Card]) -> Game {
+    let mut game = self.clone();
+    let mut used_cards = 
+      game.hands.values()
+      .flat_map(|hand| hand.0.iter().filter_map(|slot| slot.card().map(|card| card.card.clone())))
+      .chain(game.board.squares.iter().flatten().filter_map(|square| square.0.as_ref().map(|card| card.card.clone())))
+      .collect::<Vec<Card>>();
+
+    for player in [Player::Blue, Player::Red] {
+      for idx in 0..game.hands[&player].0.len() {
+        let candidates = match &game.hands[&player].0[idx] {
+          HandSlot::Unknown => pool.to_vec(),
+          HandSlot::Candidates(cards) => cards.iter().map(|card| card.card.clone()).collect(),
+          _ => continue
+        };
+        let unused_candidates = candidates.iter().filter(|card| !used_cards.contains(card)).cloned().collect::<Vec<Card>>();
+        let sampled = rng.choose(&unused_candidates).or(rng.choose(&candidates)).cloned();
+        if let Some(card) = sampled {
+          used_cards.push(card.clone());
+          game.reveal_card(GameCard { card, player: None, owner: None }, player, idx);
+        }
+      }
+    }
+    game
+  }
+
+
+
   fn remove_card_from_hand(&mut self, user_move:&Move) {
-    let card_matches = self.hands[&user_move.player].0.iter().map(|x| x == &Square::new(user_move.card.clone())).collect::<Vec<bool>>();
-    let hand_card_idx = card_matches.iter().enumerate().filter_map(|(idx, b)| (*b).then(|| idx)).nth(0).unwrap();
-    self.hands.get_mut(&user_move.player).unwrap().0[hand_card_idx] = Square(None);
+    let hand_card_idx = self.hands[&user_move.player].0.iter().position(|slot| slot.card() == Some(&user_move.card)).unwrap();
+    self.hands.get_mut(&user_move.player).unwrap().0[hand_card_idx] = HandSlot::Empty;
   }
 
 
 
   fn play_move(&mut self, user_move:&Move) -> () {
-    //println!("Playing {} card {} at {} {}!", user_move.card.player.unwrap(), user_move.card.name, user_move.coords.row.idx(), user_move.coords.col.idx());
+    //println!("Playing {} card {} at {} {}!", user_move.card.player.unwrap(), user_move.card.name, user_move.coords.row, user_move.coords.col);
     if !user_move.is_combo {
       self.remove_card_from_hand(&user_move);
     }
@@ -441,6 +973,7 @@ impl Game {
 
 
   fn flip_turn(&mut self) -> () {
+    self.forced_card = None;
     match self.turn {
       Player::Red => self.turn = Player::Blue,
       Player::Blue => self.turn = Player::Red
@@ -463,44 +996,81 @@ impl Game {
     let capturing_moves = Game::identify_captured_cards(comparisons, capturing_player);
 
     for (_card, coords) in capturing_moves {
-      self.board.0[coords.row.idx()][coords.col.idx() as usize].0.as_mut().unwrap().player = Some(*capturing_player);
+      self.board.square_mut(&coords).0.as_mut().unwrap().player = Some(*capturing_player);
     }
   }
 
 
 
-  fn calculate_card_diffs(user_move:&Move, comparisons:Vec<(Coord, GameCard, Side)>) -> Vec<(Coord, GameCard, isize)> {
-    fn card_diff(board_card:&GameCard, new_card:&GameCard, side:&Side) -> isize{
-      match side {
-        Side::Top => new_card.card.stats.bottom as isize - board_card.card.stats.top as isize,
-        Side::Right => new_card.card.stats.left as isize - board_card.card.stats.right as isize, 
-        Side::Bottom => new_card.card.stats.top as isize - board_card.card.stats.bottom as isize, 
-        Side::Left => new_card.card.stats.right as isize - board_card.card.stats.left as isize, 
-      }
-    }
+  // Stats of a card after every rule's modifier, such as Ascension or Descension for its Tribe,
+  // or Elemental for the square it's on
+  pub fn effective_stats(&self, card:&GameCard, coords:Option<&Coord>) -> CardStats {
+    let modifier = self.active_rules().map(|rule| rule.stat_modifier(self, card, coords)).sum();
+    card.card.stats.modified(modifier)
+  }
+
+
+
+  // Returns the pair of values touching along a side: (placed card's value, board card's value)
+  pub fn touching_values(&self, board_coords:&Coord, board_card:&GameCard, user_move:&Move, side:&Side) -> (isize, isize) {
+    let board_stats = self.effective_stats(board_card, Some(board_coords));
+    let board_value = match side {
+      Side::Top => board_stats.top,
+      Side::Right => board_stats.right,
+      Side::Bottom => board_stats.bottom,
+      Side::Left => board_stats.left,
+    };
+    (self.placed_value(user_move, side), board_value as isize)
+  }
+
+  // The placed card's value facing a neighbour whose touching side is `side`
+  pub fn placed_value(&self, user_move:&Move, side:&Side) -> isize {
+    let new_stats = self.effective_stats(&user_move.card, Some(&user_move.coords));
+    let value = match side {
+      Side::Top => new_stats.bottom,
+      Side::Right => new_stats.left,
+      Side::Bottom => new_stats.top,
+      Side::Left => new_stats.right,
+    };
+    value as isize
+  }
+
+
+
+  // Positive diffs mean the placed card beats the board card, once rules like Reverse have had their say
+  fn calculate_card_diffs(&self, user_move:&Move, comparisons:Vec<Comparison>) -> Vec<(Coord, GameCard, isize)> {
     comparisons
     .into_iter()
-    .map(|(coord, card, side)| {let diff = card_diff(&card, &user_move.card, &side); (coord, card, diff)})
+    .map(|(coord, card, side)| {
+      let (new_value, board_value) = self.touching_values(&coord, &card, user_move, &side);
+      let diff = self.active_rules().fold(new_value - board_value, |diff, rule| rule.capture_margin(self, new_value, board_value, diff));
+      (coord, card, diff)
+    })
     .collect::<Vec<(Coord, GameCard, isize)>>()
   }
 
 
 
-  fn resolve_card_comparisons(&mut self, user_move:&Move, comparisons:Vec<(Coord, GameCard, Side)>) -> Option<Vec<Move>>{
-    
-    fn card_sum(board_card:&GameCard, new_card:&GameCard, side:&Side) -> isize{
-      match side {
-        Side::Top => new_card.card.stats.bottom as isize + board_card.card.stats.top as isize,
-        Side::Right => new_card.card.stats.left as isize + board_card.card.stats.right as isize, 
-        Side::Bottom => new_card.card.stats.top as isize + board_card.card.stats.bottom as isize, 
-        Side::Left => new_card.card.stats.right as isize + board_card.card.stats.left as isize, 
-      }
-    }
+  // Splits off the neighbours flipped by rules like Same and Plus, which go on to combo
+  fn partition_special_comparisons(&self, user_move:&Move, comparisons:Vec<Comparison>, walls:&[Side]) -> (Vec<Comparison>, Vec<Comparison>) {
+    let special_coords = 
+      self.active_rules()
+      .flat_map(|rule| rule.combo_captures(self, user_move, &comparisons, walls))
+      .collect::<Vec<Coord>>();
+
+    comparisons
+    .into_iter()
+    .partition(|(coords, _, _)| !special_coords.contains(coords))
+  }
+
+
+
+  fn resolve_card_comparisons(&mut self, user_move:&Move, comparisons:Vec<Comparison>, walls:Vec<Side>) -> Option<Vec<Move>>{
     
     //println!("comparisons: {:?}", comparisons);
     match comparisons {
       comparisons if user_move.is_combo => {  
-        let captured_cards = Game::identify_captured_cards(Game::calculate_card_diffs(&user_move, comparisons), &user_move.player);
+        let captured_cards = Game::identify_captured_cards(self.calculate_card_diffs(user_move, comparisons), &user_move.player);
 
         let player = user_move.player;
 
@@ -509,7 +1079,7 @@ impl Game {
 
         // Remove all cards captured by combo
         for (_, coords) in &captured_cards {
-          self.board.0[coords.row.idx()][coords.col.idx()] = Square(None)
+          self.board.square_mut(coords).0 = None
         }
 
         // Add captured cards to be evaluated and continue combo
@@ -519,47 +1089,40 @@ impl Game {
           .map(|(card, coords)| 
             { let mut new_card = card.clone(); 
               new_card.player = Some(player);
-              Move {card: new_card, coords:coords, player:player, is_combo:true}}
+              Move {card: new_card, coords, player, is_combo:true}}
             )
           .collect::<Vec<Move>>();
         Some(combo_moves)
         
       }
-      //Don't check for Plus if in combo
-      comparisons if self.rules.contains(&Rule::Plus) && !user_move.is_combo => {
-        let (not_plus_comparisons, plus_comparisons) =
-          comparisons
-          .into_iter()
-          // Group all comparisons by the magnitude of their sum; same sum = Plus
-          .into_group_map_by(|(_, card, side)| card_sum(card, &user_move.card, side))
-          .iter_mut()
-          .fold( (Vec::new(), Vec::new()), 
-                |(mut not_plus_comparisons, mut plus_comparisons), (_, comparisons)| {
-                  // Categorize groups of comparisons; If a group is 2 or more elements, Plus has been achieved.
-                  // If a singleton group, no neighbors had plus, and this neighbor should be resolved normally.
-                  if comparisons.len() >= 2 { plus_comparisons.append(comparisons) }
-                  else { not_plus_comparisons.append(comparisons) } 
-                  (not_plus_comparisons, plus_comparisons)
-                }
-              );
-        //println!("non_plus_comparisons: {:?}, plus_comparisons: {:?}", not_plus_comparisons, plus_comparisons);
-
-        // Resolve all non-plus moves as normal.
-        self.capture_cards(Game::calculate_card_diffs(&user_move, not_plus_comparisons), &user_move.player);
+      // Only the original move checks rules like Plus and Same, whose flips go on to combo
+      comparisons => {
+        let (not_special_comparisons, special_comparisons) = self.partition_special_comparisons(user_move, comparisons, &walls);
+        //println!("not_special_comparisons: {:?}, special_comparisons: {:?}", not_special_comparisons, special_comparisons);
+
+        // Resolve all moves not involved in Plus or Same as normal.
+        self.capture_cards(self.calculate_card_diffs(user_move, not_special_comparisons), &user_move.player);
         
-        let moving_player = user_move.player.clone();
+        let moving_player = user_move.player;
 
         // Play the current move
         self.play_move(user_move);
 
+        // Only the opponent's cards are flipped by Plus or Same; the moving player's own cards stay put
+        let continuations = 
+          special_comparisons
+          .into_iter()
+          .filter(|(_, card, _)| card.player == Some(moving_player.other()))
+          .collect::<Vec<Comparison>>();
+
         // Check for, and handle, "Combo"
-        match plus_comparisons {
-          // Case where Plus was activated; proceed to combo
-          continuations if continuations.len() > 0 && continuations.iter().any(|(_, card, _)| card.player == Some(user_move.player.other()))=> 
+        match continuations {
+          // Case where Plus or Same was activated; proceed to combo
+          continuations if !continuations.is_empty() => 
             { 
-              // Remove all cards affected by Plus
+              // Remove all cards flipped by Plus or Same
               for (coords, _, _) in &continuations {
-                self.board.0[coords.row.idx()][coords.col.idx()] = Square(None)
+                self.board.square_mut(coords).0 = None
               }
 
               // Convert continuations to move
@@ -568,58 +1131,204 @@ impl Game {
                 .into_iter()
                 .map(|(coords, mut card, _)| {
                   card.player = Some(moving_player);
-                  Move{card:card, coords:coords, player:moving_player, is_combo:true}
+                  Move{card, coords, player:moving_player, is_combo:true}
                 })
                 .collect::<Vec<Move>>();
 
-              // Return list of replay moves to play (all the cards in plus)
+              // Return list of replay moves to play (all the cards flipped by Plus or Same)
               Some(moves)
             }
 
-          // No Plus was activated, and no combo needs to be evaluated.
+          // Neither Plus nor Same was activated, and no combo needs to be evaluated.
           _ => None
         }
       }
-
-      // No captures
-      comparisons if comparisons.is_empty() => {
-        self.play_move(user_move);
-        None
-      }
-
-      // Captures
-      comparisons => {
-        
-        self.capture_cards(Game::calculate_card_diffs(&user_move, comparisons), &user_move.player);
-        self.play_move(user_move);
-        None
-      }
     }
   }
 
 
 
-  fn compare_move_card_to_neighbors(&mut self, user_move:&Move) -> Vec<(Coord, GameCard, Side)> {
+  // Returns the neighbouring cards, and the sides facing a board edge when Same Wall or Plus Wall
+  // make the edges act as virtual A-valued neighbours
+  fn compare_move_card_to_neighbors(&mut self, user_move:&Move) -> (Vec<Comparison>, Vec<Side>) {
 
     let relative_neighbor_positions = vec![((1,0), Side::Top), ((0,1), Side::Left), ((-1,0), Side::Bottom), ((0,-1), Side::Right)];
+
+    let walls = 
+      if self.rules.contains(Rule::SameWall) || self.rules.contains(Rule::PlusWall) {
+        relative_neighbor_positions
+        .iter()
+        .filter(|((row_adj, col_adj), _)| self.board.coord(user_move.coords.row as isize + row_adj, user_move.coords.col as isize + col_adj).is_none())
+        .map(|(_, side)| *side)
+        .collect()
+      }
+      else { Vec::new() };
     
     let valid_neighbors = 
       relative_neighbor_positions
       .into_iter()
       // Apply all relative neighbor adjustments to our intended move;
-      // Coord constructor returns an Option if coords are valid; filter_map removes invalid coords.
+      // Board::coord returns an Option if coords are on the board; filter_map removes invalid coords.
       .filter_map(|((row_adj, col_adj), side)| 
         {
-          let coords = Coord::from_numbers((user_move.coords.row.idx() as isize + row_adj) as usize, (user_move.coords.col.idx() as isize + col_adj) as usize);
+          let coords = self.board.coord(user_move.coords.row as isize + row_adj, user_move.coords.col as isize + col_adj);
           coords
           .map(|x| (x, side.clone()))
           // Filter out moves whose Squares have "None" for a card
-          .and_then(|(coords, side)| self.board.0[coords.row.idx() as usize][coords.col.idx() as usize].0.clone().map(|board_card|  (coords, board_card, side)))
+          .and_then(|(coords, side)| self.board.square(&coords).0.clone().map(|board_card|  (coords, board_card, side)))
         }
       )
-      .collect::<Vec<(Coord, GameCard, Side)>>();
+      .collect::<Vec<Comparison>>();
+
+    (valid_neighbors, walls)
+  }
+
+
+
+  // The result of the round, once the board is full
+  pub fn get_result(&self) -> Option<GameResult> {
+    if !self.open_squares().is_empty() { return None }
+    match self.get_score(&Player::Blue) {
+      score if score > 0 => Some(GameResult::Win(Player::Blue)),
+      score if score < 0 => Some(GameResult::Win(Player::Red)),
+      _ => Some(GameResult::Draw)
+    }
+  }
+
+
+
+  // A hash of everything that decides the rest of the round, and nothing else: FNV over `canonical_form`.
+  // It keys data saved to disk, so it has to come out the same on every platform and Rust release, which
+  // rules out DefaultHasher and std's `Hash` impls.
+  pub fn canonical_hash(&self) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(&self.canonical_form().0);
+    hasher.finish()
+  }
+
+  // This position rotated or reflected by `symmetry`, with every card's sides turned to match
+  pub fn transformed(&self, symmetry:Symmetry) -> Game {
+    let mut game = self.clone();
+    let transform_card = |card:&mut GameCard| card.card.stats = symmetry.apply_to_stats(&card.card.stats);
+    for coords in self.board.coords() {
+      let mut square = self.board.square(&coords).clone();
+      square.0.iter_mut().for_each(transform_card);
+      *game.board.square_mut(&symmetry.apply(&self.board, &coords)) = square;
+    }
+    for slot in game.hands.values_mut().flat_map(|hand| hand.0.iter_mut()) {
+      match slot {
+        HandSlot::Known(card) => transform_card(card),
+        HandSlot::Candidates(cards) => cards.iter_mut().for_each(transform_card),
+        _ => ()
+      }
+    }
+    game
+  }
+
+  // Turns the position to its canonical orientation, so rotations and reflections of it become identical
+  pub fn canonicalize(&mut self) {
+    let (_, symmetry) = self.canonical_form();
+    if symmetry != Symmetry::default() {
+      *self = self.transformed(symmetry);
+    }
+  }
+
+  // The position written out as bytes, and the symmetry that takes it to its canonical orientation. Cards are
+  // written by their stats and element rather than their name or id, and colours relative to the player to move,
+  // so colour-swapped positions match. Rotations and reflections match too: every orientation is written and the
+  // smallest kept. Hands are only written for the orientations whose boards tie for the smallest, usually just one.
+  pub fn canonical_form(&self) -> (Vec<u8>, Symmetry) {
+    let boards = Symmetry::all(&self.board).map(|symmetry| (self.board_bytes(symmetry), symmetry)).collect::<Vec<(Vec<u8>, Symmetry)>>();
+    let smallest = boards.iter().map(|(bytes, _)| bytes).min().unwrap().clone();
+    boards
+      .into_iter()
+      .filter(|(bytes, _)| *bytes == smallest)
+      .map(|(mut bytes, symmetry)| { self.write_hands(symmetry, &mut bytes); (bytes, symmetry) })
+      .min_by(|(a, _), (b, _)| a.cmp(b))
+      .unwrap()
+  }
+
+  // Cards are written by what matters for play, with their sides turned to match the board
+  fn write_card(&self, card:&GameCard, symmetry:Symmetry, bytes:&mut Vec<u8>) {
+    write_card_face(&card.card, symmetry, bytes);
+    bytes.push(match card.player { None => 0, Some(player) if player == self.turn => 1, Some(_) => 2 });
+  }
+
+  // The board as it would look transformed by `symmetry`
+  fn board_bytes(&self, symmetry:Symmetry) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_u32(&mut bytes, self.board.rows());
+    write_u32(&mut bytes, self.board.cols());
+    for coords in self.board.coords() {
+      let square = self.board.square(&symmetry.invert(&self.board, &coords));
+      match &square.0 {
+        Some(card) => { bytes.push(1); self.write_card(card, symmetry, &mut bytes) }
+        None => bytes.push(0)
+      }
+      bytes.push(element_tag(square.1));
+    }
+    self.board.tribe_counts.iter().for_each(|count| write_u32(&mut bytes, *count));
+    bytes
+  }
+
+  // Everything else, mover's hand first
+  fn write_hands(&self, symmetry:Symmetry, bytes:&mut Vec<u8>) {
+    for player in [self.turn, self.turn.other()] {
+      write_u32(bytes, self.hands[&player].0.len());
+      for slot in &self.hands[&player].0 {
+        match slot {
+          HandSlot::Empty => bytes.push(0),
+          HandSlot::Known(card) => { bytes.push(1); self.write_card(card, symmetry, bytes) }
+          HandSlot::Unknown => bytes.push(2),
+          HandSlot::Candidates(cards) => {
+            bytes.push(3);
+            write_u32(bytes, cards.len());
+            cards.iter().for_each(|card| self.write_card(card, symmetry, bytes))
+          }
+        }
+      }
+    }
+    write_u32(bytes, self.forced_card.map_or(0, |idx| idx + 1));
+    bytes.extend(self.rules.0.to_le_bytes());
+    let names = self.house_rules.names();
+    write_u32(bytes, names.len());
+    for name in names {
+      write_u32(bytes, name.len());
+      bytes.extend(name.as_bytes());
+    }
+  }
+
+
+
+  // What the rules make of a full board; Sudden Death replays draws
+  fn round_end(&self, result:GameResult) -> RoundEnd {
+    self.active_rules().find_map(|rule| rule.end_of_round(self, result)).unwrap_or(RoundEnd::Finished(result))
+  }
+
+
 
-    valid_neighbors
+  // Sudden Death: each player's new hand is every card they control, and the other player goes first
+  fn start_next_round(&mut self) {
+    for player in [Player::Blue, Player::Red] {
+      let held_slots = self.hands[&player].0.iter().filter(|slot| !slot.is_empty()).cloned();
+      let captured_slots = 
+        self.board.squares.iter().flatten()
+        .filter_map(|square| square.0.clone())
+        .filter(|card| card.player == Some(player))
+        .map(HandSlot::Known);
+      let mut new_hand = Hand::new(self.hand_size());
+      for (idx, slot) in held_slots.chain(captured_slots).take(self.hand_size()).enumerate() {
+        new_hand.0[idx] = slot;
+      }
+      self.hands.insert(player, new_hand);
+    }
+    // Elemental squares stay where they were
+    let squares = self.board.squares.iter().map(|row| row.iter().map(|square| Square(None, square.1)).collect()).collect();
+    self.board = Board { squares, tribe_counts: [0; 4] };
+    self.forced_card = None;
+    self.round += 1;
+    self.first_player = self.first_player.other();
+    self.turn = self.first_player;
   }
 
 
@@ -628,8 +1337,13 @@ impl Game {
     if self.is_valid_move(&user_move) {
       let is_combo = user_move.is_combo;
 
-      let card_comparisons = self.compare_move_card_to_neighbors(&user_move);
-      let combos = self.resolve_card_comparisons(user_move, card_comparisons);
+      // Combos re-place cards already on the board, so only count newly placed cards
+      if let (false, Some(tribe)) = (is_combo, user_move.card.card.stats.tribe) {
+        self.board.tribe_counts[tribe.idx()] += 1;
+      }
+
+      let (card_comparisons, walls) = self.compare_move_card_to_neighbors(user_move);
+      let combos = self.resolve_card_comparisons(user_move, card_comparisons, walls);
 
       match combos {
         Some(combos) =>
@@ -641,16 +1355,18 @@ impl Game {
 
       if !is_combo { self.flip_turn()}
 
-      // Return result
-      if self.open_squares().len() > 0 { Some(MoveResult::NextMove) }
-      else {
-        match self.get_score(&Player::Blue) {
-          score if score == 0 => Some(MoveResult::Finished(GameResult::Draw)),
-          score if score > 0 => Some(MoveResult::Finished(GameResult::Win(Player::Blue))),
-          score if score < 0 => Some(MoveResult::Finished(GameResult::Win(Player::Red))),
-          _ => Some(MoveResult::Finished(GameResult::Draw))
-        }}
-      
+      // Return result; combos resolve within a single move, so only the original move can end the round
+      match self.get_result() {
+        Some(result) if !is_combo => match self.round_end(result) {
+          RoundEnd::Replay => {
+            self.start_next_round();
+            Some(MoveResult::NextRound)
+          }
+          RoundEnd::Finished(result) => Some(MoveResult::Finished(result))
+        },
+        _ => Some(MoveResult::NextMove)
+      }
+
     }
     else {
       None
@@ -660,18 +1376,36 @@ impl Game {
 
 
 }
+// A step in the search tree: either a player placing a card, or Chaos selecting a card (a chance event)
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Action
+{ Play(Move)
+, Force(usize)
+}
+
 impl GameState for Game {
-  type Move = Move;
+  type Move = Action;
   type Player = Player;
-  type MoveList = Vec<Move>;
+  type MoveList = Vec<Action>;
   fn current_player(&self) -> Self::Player {
     self.turn
   }
-  fn available_moves(&self) -> Vec<Move> {
-    self.get_valid_moves()
+  fn available_moves(&self) -> Vec<Action> {
+    if self.awaiting_chaos() {
+      self.hands[&self.turn].occupied_idxs().into_iter().map(Action::Force).collect()
+    }
+    else {
+      self.get_valid_moves().into_iter().map(Action::Play).collect()
+    }
   }
+  // Each position in the tree is kept in its canonical orientation, so transposed nodes hold moves legal in
+  // every position that reaches them. Only the root keeps the orientation of the real board.
   fn make_move(&mut self, mov:&Self::Move) {
-    self.make_move(mov);
+    match mov {
+      Action::Play(user_move) => { self.make_move(user_move); }
+      Action::Force(idx) => { self.force_card(*idx); }
+    }
+    self.canonicalize();
   }
 
 }
@@ -681,25 +1415,67 @@ impl GameState for Game {
 ////////////////////////////////////////////
 
 
-const DEFAULT_SCORE: isize = 89;
-const DRAW_SCORE: isize = 80;
-const WIN_SCORE: isize = 120;
-const LOSS_SCORE: isize = 40;
-
-const WIN_POINTS: isize = WIN_SCORE - DEFAULT_SCORE;
-const DRAW_POINTS: isize = DRAW_SCORE - DEFAULT_SCORE;
-const LOSS_POINTS: isize = LOSS_SCORE - DEFAULT_SCORE;
-
-const SAFETY_FACTOR: f32 = 0.2;
+// Under AvoidLoss, a draw is worth this much of a win
+const AVOID_LOSS_DRAW_VALUE: f32 = 0.5;
+// Under WinProbability and AvoidLoss, a bigger margin still breaks ties between equally good results
+const MARGIN_TIEBREAK: f32 = 0.1;
+// How much a trade's star balance counts next to the result, relative to winning a whole hand's worth
+const TRADE_RISK_WEIGHT: f32 = 0.25;
+// mcts sums rewards as integers, so evaluations are scaled up before they're handed over
+pub const EVALUATION_SCALE: f32 = 1000.0;
+
+// What the AI plays for, on behalf of `Game.my_color`; the other player is assumed to play against it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Objective
+{ #[default] WinProbability
+// Capture as many cards as possible, even at some risk to the result
+, Margin
+// Treat a draw as nearly as good as a win
+, AvoidLoss
+}
+impl FromStr for Objective {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "win" | "winprobability" => Ok(Objective::WinProbability),
+      "margin" => Ok(Objective::Margin),
+      "safe" | "avoidloss" => Ok(Objective::AvoidLoss),
+      _ => Err(format!("Unknown objective \"{}\"", s.trim()))
+    }
+  }
+}
+impl Objective {
+  // The value of finishing `margin` cards ahead (or behind, if negative), scaled to about -1..=1
+  pub fn utility(self, margin:isize, max_margin:isize) -> f32 {
+    let scaled_margin = margin as f32 / max_margin.max(1) as f32;
+    let result = match margin {
+      margin if margin > 0 => 1.0,
+      margin if margin < 0 => -1.0,
+      _ if self == Objective::AvoidLoss => AVOID_LOSS_DRAW_VALUE,
+      _ => 0.0
+    };
+    match self {
+      Objective::Margin => scaled_margin,
+      _ => (1.0 - MARGIN_TIEBREAK) * result + MARGIN_TIEBREAK * scaled_margin
+    }
+  }
+}
 
+// The search tree shares a node between positions with the same hash, so this hashes the exact position, colours
+// and all: node values are stored for Blue. Rotations and reflections still meet here, since the search turns
+// every position to its canonical orientation as it moves (see `GameState::make_move`).
 impl TranspositionHash for Game {
   fn hash(&self) -> u64 {
     let mut hasher = DefaultHasher::new();
     self.board.hash(&mut hasher);
-    for hand in self.hands.values() {
-      hand.hash(&mut hasher);
+    for player in [Player::Blue, Player::Red] {
+      self.hands[&player].hash(&mut hasher);
     }
     self.first_player.hash(&mut hasher);
+    self.forced_card.hash(&mut hasher);
+    self.round.hash(&mut hasher);
+    self.rules.hash(&mut hasher);
+    self.house_rules.hash(&mut hasher);
 
     hasher.finish()
   }
@@ -707,23 +1483,42 @@ impl TranspositionHash for Game {
 
 
 
-pub struct MyEvaluator;
+// Scores positions by the cards each player controls, as `my_color` would value the result if the game ended there
+#[derive(Clone, Debug, Default)]
+pub struct MyEvaluator
+{ pub objective: Objective
+// Late positions take their exact value from here, instead of the cards each player controls now
+, pub tablebase: Option<Arc<Tablebase>>
+}
+impl MyEvaluator {
+  #[allow(dead_code)]
+  pub fn new(objective:Objective) -> MyEvaluator {
+    MyEvaluator { objective, tablebase: None }
+  }
+
+  pub fn with_tablebase(objective:Objective, tablebase:Arc<Tablebase>) -> MyEvaluator {
+    MyEvaluator { objective, tablebase: Some(tablebase) }
+  }
+}
 impl Evaluator<MyMCTS> for MyEvaluator {
+  // The position's value to Blue; the search is zero-sum, so Red's value is its negation
   type StateEvaluation = f32;
 
-  fn evaluate_new_state(&self, state: &Game, moves: &Vec<Move>, _: Option<SearchHandle<MyMCTS>>) -> (Vec<()>, f32) {
-    let score = state.get_score(&Player::Blue);
-    fn score_factor(score:isize) -> f32 {
-      ((score.abs() - 1) as f32 * SAFETY_FACTOR) + 1.0
-    } 
-    let score = match score {
-      score if score > 0 => score_factor(score) * WIN_POINTS as f32,
-      score if score < 0 => score_factor(score) * DRAW_POINTS as f32,
-      score if score == 0 => score_factor(score) * LOSS_POINTS as f32,
-      _ => -100.0
+  fn evaluate_new_state(&self, state: &Game, moves: &Vec<Action>, _: Option<SearchHandle<MyMCTS>>) -> (Vec<()>, f32) {
+    let me = state.my_color;
+    let margin = match self.tablebase.as_ref().and_then(|tablebase| tablebase.value(state)) {
+      Some(margin) if state.turn == me => margin,
+      Some(margin) => -margin,
+      None => state.get_score(&me) - state.get_score(&me.other())
+    };
+    // Every card in play, including the one left in hand, can end up on one side
+    let max_margin = 2 * state.hand_size() as isize;
+    let trade_risk = match state.trade_rule {
+      Some(rule) => TRADE_RISK_WEIGHT * projected_trade(state, rule).value_for(me) as f32 / (state.hand_size() * 5) as f32,
+      None => 0.0
     };
-    let score_range = (score_factor(4) * WIN_POINTS as f32) - (score_factor(-4) * LOSS_POINTS as f32);
-    (vec![(); moves.len()], (score/score_range) as f32)
+    let value = self.objective.utility(margin, max_margin) + trade_risk;
+    (vec![(); moves.len()], if me == Player::Blue { value } else { -value })
   }
 
   fn evaluate_existing_state(&self, _state: &<MyMCTS as MCTS>::State, existing_evaln: &Self::StateEvaluation,
@@ -734,9 +1529,52 @@ impl Evaluator<MyMCTS> for MyEvaluator {
 
   fn interpret_evaluation_for_player(&self,
           evaluation: &Self::StateEvaluation,
-          _player: &mcts::Player<MyMCTS>) -> i64 {
-          (*evaluation * 4.0) as i64
+          player: &mcts::Player<MyMCTS>) -> i64 {
+          let evaluation = match player {
+            Player::Blue => *evaluation,
+            Player::Red => -*evaluation
+          };
+          (evaluation * EVALUATION_SCALE).round() as i64
+      }
+}
+
+// UCT, except at chance nodes (Chaos selecting a card) where children are sampled uniformly
+#[derive(Clone, Debug)]
+pub struct ChanceUCTPolicy
+{ exploration_constant: f64
+}
+impl ChanceUCTPolicy {
+  pub fn new(exploration_constant: f64) -> Self {
+    assert!(exploration_constant > 0.0, "exploration constant is {} (must be positive)", exploration_constant);
+    Self { exploration_constant }
+  }
+}
+impl TreePolicy<MyMCTS> for ChanceUCTPolicy {
+  type MoveEvaluation = ();
+  type ThreadLocalData = PolicyRng;
+
+  fn choose_child<'a, MoveIter>(&self, moves: MoveIter, mut handle: SearchHandle<MyMCTS>) -> &'a MoveInfo<MyMCTS>
+    where MoveIter: Iterator<Item=&'a MoveInfo<MyMCTS>> + Clone
+  {
+    let is_chance_node = moves.clone().all(|mov| matches!(mov.get_move(), Action::Force(_)));
+    if is_chance_node {
+      // Every key ties, so the policy's seeded RNG picks uniformly among the outcomes
+      return handle.thread_data().policy_data.select_by_key(moves, |_| 0.0).unwrap();
+    }
+
+    let total_visits = moves.clone().map(|x| x.visits()).sum::<u64>();
+    let ln_adjusted_total = ((total_visits + 1) as f64).ln();
+    handle.thread_data().policy_data.select_by_key(moves, |mov| {
+      match mov.visits() {
+        0 => f64::INFINITY,
+        child_visits => {
+          let explore_term = 2.0 * (ln_adjusted_total / child_visits as f64).sqrt();
+          let mean_action_value = mov.sum_rewards() as f64 / (child_visits as f64 * EVALUATION_SCALE as f64);
+          self.exploration_constant * explore_term + mean_action_value
+        }
       }
+    }).unwrap()
+  }
 }
 
 #[derive(Default)]
@@ -746,10 +1584,359 @@ impl MCTS for MyMCTS {
   type Eval = MyEvaluator;
   type NodeData = ();
   type ExtraThreadData = ();
-  type TreePolicy = UCTPolicy;
+  type TreePolicy = ChanceUCTPolicy;
   type TranspositionTable = ApproxTable<Self>;
 
   fn cycle_behaviour(&self) -> CycleBehaviour<Self> {
       CycleBehaviour::PanicWhenCycleDetected
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{SeedableRng, StdRng};
+
+  fn card(top:usize, right:usize, bottom:usize, left:usize) -> GameCard {
+    let stats = CardStats { top, right, bottom, left, tribe: None };
+    GameCard { card: Card { name: format!("{} {} {} {}", top, right, bottom, left), id: 0, stars: 1, stats, element: None }, player: None, owner: None }
+  }
+
+  fn place(game:&mut Game, mut card:GameCard, player:Player, row:usize, col:usize) {
+    card.player = Some(player);
+    card.owner = Some(player);
+    game.board.squares[row][col] = Square(Some(card), None);
+  }
+
+  fn play(game:&mut Game, card:GameCard, row:usize, col:usize) {
+    let player = game.turn;
+    game.add_card_to_hand(card, player);
+    let card = game.hands[&player].0[0].card().cloned().unwrap();
+    game.make_move(&Move::new(card, Coord::new(row, col), player)).unwrap();
+  }
+
+  fn controller(game:&Game, row:usize, col:usize) -> Option<Player> {
+    game.board.squares[row][col].0.as_ref().and_then(|card| card.player)
+  }
+
+  fn game(rules:&[Rule]) -> Game {
+    Game::new(Player::Blue, Player::Blue, rules.iter().copied().collect())
+  }
+
+  #[test]
+  fn same_at_an_edge_square() {
+    let mut game = game(&[Rule::Same]);
+    // Placed at the top middle: matching the card to the left and the card below, not the weaker card to the right
+    place(&mut game, card(1, 4, 1, 1), Player::Red, 0, 0);
+    place(&mut game, card(6, 1, 1, 1), Player::Red, 1, 1);
+    place(&mut game, card(1, 1, 1, 9), Player::Red, 0, 2);
+    play(&mut game, card(1, 2, 6, 4), 0, 1);
+    assert_eq!(controller(&game, 0, 0), Some(Player::Blue));
+    assert_eq!(controller(&game, 1, 1), Some(Player::Blue));
+    assert_eq!(controller(&game, 0, 2), Some(Player::Red));
+  }
+
+  #[test]
+  fn same_needs_two_matches() {
+    let mut game = game(&[Rule::Same]);
+    place(&mut game, card(1, 1, 1, 5), Player::Red, 0, 1);
+    play(&mut game, card(1, 5, 3, 1), 0, 0);
+    assert_eq!(controller(&game, 0, 1), Some(Player::Red));
+  }
+
+  #[test]
+  fn same_and_plus_together() {
+    let mut game = game(&[Rule::Same, Rule::Plus]);
+    // Same above and to the left, Plus (sums of 8) to the right and below
+    place(&mut game, card(1, 1, 5, 1), Player::Red, 0, 1);
+    place(&mut game, card(1, 5, 1, 1), Player::Red, 1, 0);
+    place(&mut game, card(1, 1, 1, 6), Player::Red, 1, 2);
+    place(&mut game, card(5, 1, 1, 1), Player::Red, 2, 1);
+    play(&mut game, card(5, 2, 3, 5), 1, 1);
+    for (row, col) in [(0, 1), (1, 0), (1, 2), (2, 1)] {
+      assert_eq!(controller(&game, row, col), Some(Player::Blue));
+    }
+  }
+
+  #[test]
+  fn same_chains_into_a_combo() {
+    let mut game = game(&[Rule::Same]);
+    place(&mut game, card(1, 1, 1, 5), Player::Red, 0, 1);
+    // Flipped by Same, then its 9 captures the card to its right as a combo
+    place(&mut game, card(3, 9, 1, 1), Player::Red, 1, 0);
+    place(&mut game, card(1, 1, 1, 2), Player::Red, 1, 1);
+    // Touched only by weaker or equal sides, so they stay put
+    place(&mut game, card(1, 1, 1, 1), Player::Red, 0, 2);
+    place(&mut game, card(1, 1, 1, 1), Player::Red, 2, 1);
+    play(&mut game, card(1, 5, 3, 1), 0, 0);
+    assert_eq!(controller(&game, 0, 1), Some(Player::Blue));
+    assert_eq!(controller(&game, 1, 0), Some(Player::Blue));
+    assert_eq!(controller(&game, 1, 1), Some(Player::Blue));
+    assert_eq!(controller(&game, 0, 2), Some(Player::Red));
+    assert_eq!(controller(&game, 2, 1), Some(Player::Red));
+  }
+
+  #[test]
+  fn own_cards_count_toward_same() {
+    let mut game = game(&[Rule::Same]);
+    place(&mut game, card(1, 1, 1, 5), Player::Blue, 0, 1);
+    place(&mut game, card(3, 1, 1, 1), Player::Red, 1, 0);
+    play(&mut game, card(1, 5, 3, 1), 0, 0);
+    assert_eq!(controller(&game, 0, 1), Some(Player::Blue));
+    assert_eq!(controller(&game, 1, 0), Some(Player::Blue));
+    assert_eq!(game.board.squares[0][1].0.as_ref().unwrap().owner, Some(Player::Blue));
+  }
+
+  #[test]
+  fn conflicting_rules_are_rejected() {
+    for (a, b) in CONFLICTING_RULES {
+      let rules = RuleSet::new().with(a).with(b);
+      assert_eq!(rules.conflicts(), [(a, b)]);
+      assert_eq!(rules.validate(), Err(format!("Conflicting rules: {:?} and {:?}", a, b)));
+      assert!(format!("{:?}, {:?}", a, b).parse::<RuleSet>().is_err());
+      assert_eq!(rules.without(b).validate(), Ok(RuleSet::new().with(a)));
+    }
+  }
+
+  #[test]
+  fn rule_sets_round_trip_through_display() {
+    let everything_compatible = Rule::ALL.into_iter().filter(|rule| !CONFLICTING_RULES.iter().any(|(_, b)| b == rule)).collect::<RuleSet>();
+    assert!(everything_compatible.validate().is_ok());
+    for rules in [RuleSet::new(), RuleSet::new().with(Rule::FallenAce), everything_compatible] {
+      assert_eq!(rules.to_string().parse::<RuleSet>(), Ok(rules));
+    }
+    assert_eq!("all open, Plus,fallen_ace".parse::<RuleSet>(), Ok([Rule::AllOpen, Rule::Plus, Rule::FallenAce].into_iter().collect()));
+  }
+
+  #[test]
+  fn unknown_rules_are_errors() {
+    assert_eq!("plus, double plus".parse::<RuleSet>(), Err("Unknown rule \"double plus\"".to_string()));
+    assert_eq!("".parse::<RuleSet>(), Ok(RuleSet::new()));
+  }
+
+  fn chaos_game() -> Game {
+    let mut game = game(&[Rule::Chaos]);
+    for idx in 0..5 {
+      game.add_card_to_hand(card(idx + 1, 2, 3, 4), Player::Blue);
+      game.add_card_to_hand(card(4, 3, 2, idx + 1), Player::Red);
+    }
+    game
+  }
+
+  // The cards Chaos picks over a whole game, playing each into the first open square
+  fn forced_sequence(seed:usize) -> Vec<usize> {
+    let mut game = chaos_game();
+    let mut rng = StdRng::from_seed(&[seed][..]);
+    let mut picks = Vec::new();
+    while game.awaiting_chaos() {
+      let idx = game.roll_forced_card(&mut rng).unwrap();
+      picks.push(idx);
+      let card = game.hands[&game.turn].0[idx].card().cloned().unwrap();
+      let user_move = Move::new(card, game.open_squares()[0], game.turn);
+      game.make_move(&user_move).unwrap();
+    }
+    picks
+  }
+
+  #[test]
+  fn seeded_chaos_is_reproducible() {
+    let picks = forced_sequence(7);
+    assert_eq!(picks.len(), 9);
+    assert_eq!(forced_sequence(7), picks);
+  }
+
+  #[test]
+  fn chaos_offers_only_forced_picks_until_a_card_is_chosen() {
+    let mut game = chaos_game();
+    assert!(game.awaiting_chaos());
+    let actions = game.available_moves();
+    assert_eq!(actions.len(), 5);
+    assert!(actions.iter().all(|action| matches!(action, Action::Force(_))));
+
+    game.force_card(2).unwrap();
+    assert!(!game.awaiting_chaos());
+    let forced = game.hands[&Player::Blue].0[2].card().cloned().unwrap();
+    let actions = game.available_moves();
+    assert_eq!(actions.len(), 9);
+    assert!(actions.iter().all(|action| matches!(action, Action::Play(user_move) if user_move.card == forced)));
+  }
+
+  // Plays `placed` at the top middle square, next to a Red card on its left, and says whether that card was taken
+  fn captures_left_neighbour(rules:&[Rule], neighbour:GameCard, placed:GameCard) -> bool {
+    let mut game = game(rules);
+    place(&mut game, neighbour, Player::Red, 0, 0);
+    play(&mut game, placed, 0, 1);
+    controller(&game, 0, 0) == Some(Player::Blue)
+  }
+
+  #[test]
+  fn same_wall_counts_the_edge_as_an_a() {
+    // The placed card's A faces the top edge, and its left matches the neighbour's right
+    assert!(captures_left_neighbour(&[Rule::Same, Rule::SameWall], card(1, 4, 1, 10), card(10, 1, 1, 4)));
+    assert!(!captures_left_neighbour(&[Rule::Same], card(1, 4, 1, 10), card(10, 1, 1, 4)));
+    // Without an A against the wall, one match isn't enough
+    assert!(!captures_left_neighbour(&[Rule::Same, Rule::SameWall], card(1, 4, 1, 10), card(9, 1, 1, 4)));
+  }
+
+  #[test]
+  fn plus_wall_adds_the_edge_as_an_a() {
+    // 2 against the wall makes 12, as does 5 against the neighbour's 7
+    assert!(captures_left_neighbour(&[Rule::Plus, Rule::PlusWall], card(1, 7, 1, 10), card(2, 1, 1, 5)));
+    assert!(!captures_left_neighbour(&[Rule::Plus], card(1, 7, 1, 10), card(2, 1, 1, 5)));
+    assert!(!captures_left_neighbour(&[Rule::Plus, Rule::PlusWall], card(1, 7, 1, 10), card(3, 1, 1, 5)));
+  }
+
+  #[test]
+  fn wall_rules_need_their_base_rule() {
+    assert!("same wall".parse::<RuleSet>().is_err());
+    assert!("plus wall".parse::<RuleSet>().is_err());
+    assert!("same, plus wall".parse::<RuleSet>().is_err());
+    assert!("same, same wall, plus, plus wall".parse::<RuleSet>().is_ok());
+  }
+
+  // Blue controls `blue` of the board's cards and Red the rest, with one card left in Blue's hand
+  fn full_board(blue:usize, my_color:Player) -> Game {
+    let mut game = Game::new(Player::Blue, my_color, RuleSet::new());
+    for (idx, coords) in game.board.coords().collect::<Vec<Coord>>().into_iter().enumerate() {
+      let player = if idx < blue { Player::Blue } else { Player::Red };
+      place(&mut game, card(1, 1, 1, 1), player, coords.row, coords.col);
+    }
+    game.add_card_to_hand(card(2, 2, 2, 2), Player::Blue);
+    game
+  }
+
+  fn evaluate(objective:Objective, game:&Game, player:Player) -> i64 {
+    let evaluator = MyEvaluator::new(objective);
+    let (_, evaluation) = evaluator.evaluate_new_state(game, &game.available_moves(), None);
+    evaluator.interpret_evaluation_for_player(&evaluation, &player)
+  }
+
+  #[test]
+  fn red_evaluation_is_the_negation_of_blue() {
+    for objective in [Objective::WinProbability, Objective::Margin, Objective::AvoidLoss] {
+      for my_color in [Player::Blue, Player::Red] {
+        let game = full_board(6, my_color);
+        assert_eq!(evaluate(objective, &game, Player::Red), -evaluate(objective, &game, Player::Blue));
+      }
+      assert!(evaluate(objective, &full_board(6, Player::Red), Player::Red) < 0);
+    }
+    // Zero-sum objectives value a position the same whichever side we play
+    for objective in [Objective::WinProbability, Objective::Margin] {
+      assert_eq!(evaluate(objective, &full_board(6, Player::Blue), Player::Blue), evaluate(objective, &full_board(6, Player::Red), Player::Blue));
+    }
+  }
+
+  #[test]
+  fn win_beats_draw_beats_loss() {
+    for objective in [Objective::WinProbability, Objective::Margin, Objective::AvoidLoss] {
+      for max_margin in [10, 16] {
+        let utility = |margin| objective.utility(margin, max_margin);
+        assert!(utility(1) > utility(0), "{:?}", objective);
+        assert!(utility(0) > utility(-1), "{:?}", objective);
+        assert!(utility(max_margin) > utility(1) && utility(-1) > utility(-max_margin), "{:?}", objective);
+      }
+    }
+  }
+
+  #[test]
+  fn a_clean_sweep_scores_the_full_margin() {
+    // Blue holds all nine board cards and the card in hand: +10 on 3x3
+    let game = full_board(9, Player::Blue);
+    assert_eq!(evaluate(Objective::Margin, &game, Player::Blue), EVALUATION_SCALE as i64);
+  }
+
+  #[test]
+  fn canonical_hash_is_pinned() {
+    // Saved tablebases and opening books are keyed by this hash; if it has to change, bump their versions too
+    let game = full_board(6, Player::Blue);
+    assert_eq!(game.canonical_hash(), 13180358368571392306);
+    // Every field is written out explicitly, so elements and hands are pinned too
+    assert_eq!(lopsided_position(Board::new(3, 3)).canonical_hash(), 18135489230636967231);
+  }
+
+  // Cards with four different sides in a corner and off-centre, an element, and a card in each hand
+  fn lopsided_position(board:Board) -> Game {
+    let mut game = Game::with_board(board, Player::Blue, Player::Blue, [Rule::Elemental].into_iter().collect());
+    place(&mut game, card(1, 2, 3, 4), Player::Red, 0, 0);
+    place(&mut game, card(5, 6, 7, 8), Player::Blue, 1, 1);
+    game.board.squares[0][1].1 = Some(Element::Fire);
+    game.add_card_to_hand(card(9, 1, 2, 3), Player::Blue);
+    game.add_card_to_hand(card(2, 3, 4, 5), Player::Red);
+    game
+  }
+
+  const QUARTER_TURN: Symmetry = Symmetry { flip_rows: true, flip_cols: false, transpose: true };
+
+  #[test]
+  fn symmetries_map_squares_back_and_forth() {
+    for (board, count) in [(Board::new(3, 3), 8), (Board::new(3, 4), 4)] {
+      let images = Symmetry::all(&board).map(|symmetry| {
+        for coords in board.coords() {
+          assert_eq!(symmetry.invert(&board, &symmetry.apply(&board, &coords)), coords);
+        }
+        board.coords().map(|coords| symmetry.apply(&board, &coords)).collect::<Vec<Coord>>()
+      });
+      assert_eq!(images.unique().count(), count);
+    }
+    let board = Board::new(3, 3);
+    assert_eq!(QUARTER_TURN.apply(&board, &Coord::new(0, 0)), Coord::new(0, 2));
+    assert_eq!(QUARTER_TURN.apply(&board, &Coord::new(0, 1)), Coord::new(1, 2));
+  }
+
+  #[test]
+  fn equivalent_positions_hash_the_same() {
+    for (board, count) in [(Board::new(3, 3), 8), (Board::new(3, 4), 4)] {
+      let game = lopsided_position(board.clone());
+      assert_eq!(Symmetry::all(&board).count(), count);
+      for symmetry in Symmetry::all(&board) {
+        assert_eq!(game.transformed(symmetry).canonical_hash(), game.canonical_hash(), "{:?}", symmetry);
+      }
+    }
+  }
+
+  #[test]
+  fn card_sides_turn_with_the_board() {
+    // Turned clockwise, the card's left side ends up on top
+    let turned = QUARTER_TURN.apply_to_stats(&card(1, 2, 3, 4).card.stats);
+    assert_eq!((turned.top, turned.right, turned.bottom, turned.left), (4, 1, 2, 3));
+
+    // Moving the cards without turning them is a different position
+    let game = lopsided_position(Board::new(3, 3));
+    let mut unturned = game.clone();
+    for coords in game.board.coords() {
+      *unturned.board.square_mut(&QUARTER_TURN.apply(&game.board, &coords)) = game.board.square(&coords).clone();
+    }
+    assert_ne!(unturned.canonical_hash(), game.canonical_hash());
+  }
+
+  #[test]
+  fn different_positions_hash_differently() {
+    let game = lopsided_position(Board::new(3, 3));
+    // An edge square is no rotation of a corner
+    let mut moved = game.clone();
+    moved.board.squares[0][1].0 = moved.board.squares[0][0].0.take();
+    assert_ne!(moved.canonical_hash(), game.canonical_hash());
+
+    let mut flipped = game.clone();
+    flipped.board.squares[0][0].0.as_mut().unwrap().player = Some(Player::Blue);
+    assert_ne!(flipped.canonical_hash(), game.canonical_hash());
+  }
+
+  #[test]
+  fn search_shares_turned_positions() {
+    let game = lopsided_position(Board::new(3, 3));
+    let turned = game.transformed(QUARTER_TURN);
+    for action in game.available_moves() {
+      let Action::Play(user_move) = action else { panic!("{:?}", action) };
+      let card = turned.hands[&Player::Blue].0[0].card().cloned().unwrap();
+      let turned_move = Move::new(card, QUARTER_TURN.apply(&game.board, &user_move.coords), Player::Blue);
+
+      let mut child = game.clone();
+      GameState::make_move(&mut child, &Action::Play(user_move));
+      let mut turned_child = turned.clone();
+      GameState::make_move(&mut turned_child, &Action::Play(turned_move));
+      assert_eq!(TranspositionHash::hash(&child), TranspositionHash::hash(&turned_child));
+      assert_eq!(child.available_moves(), turned_child.available_moves());
+    }
+  }
+}
@@ -0,0 +1,46 @@
+use std::{collections::HashMap, time::Duration};
+use itertools::Itertools;
+use rand::Rng;
+
+use crate::{game::*, search::timed_search, types::Card};
+
+// A root move's search statistics, summed over every determinization
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MoveStats
+{ pub visits: u64
+, pub sum_rewards: i64
+}
+impl MoveStats {
+  // The average value of the move to the player making it, on the evaluator's -1..=1 scale
+  pub fn mean_value(&self) -> f32 {
+    self.sum_rewards as f32 / (self.visits.max(1) as f32 * EVALUATION_SCALE)
+  }
+}
+
+// Every card of one star tier, for opponents whose deck we don't know
+pub fn star_tier_pool(cardlist:&[Card], stars:usize) -> Vec<Card> {
+  cardlist.iter().filter(|card| card.stars == stars).cloned().collect()
+}
+
+// Determinized MCTS: fills the hidden cards from `pool` (an NPC's deck, a star tier, or the whole cardlist)
+// `determinizations` times, searches each sample for `time_per_sample`, and sums the root moves' statistics across samples.
+// A move that does well against most of the hands the opponent could hold comes out on top.
+// Returns every root move, most visited first.
+pub fn determinized_search<R: Rng>(game:&Game, pool:&[Card], rng:&mut R, determinizations:usize, time_per_sample:Duration, threads:usize, evaluator:&MyEvaluator) -> Vec<(Action, MoveStats)> {
+  let mut stats: HashMap<Action, MoveStats> = HashMap::new();
+
+  for _ in 0..determinizations {
+    let sample = game.determinize(rng, pool);
+    let mcts = timed_search(&sample, evaluator.clone(), time_per_sample, threads, |_| ());
+    for info in mcts.tree().root_node().moves() {
+      let entry = stats.entry(info.get_move().clone()).or_default();
+      entry.visits += info.visits();
+      entry.sum_rewards += info.sum_rewards();
+    }
+  }
+
+  stats
+    .into_iter()
+    .sorted_by_key(|(_, stats)| std::cmp::Reverse(stats.visits))
+    .collect()
+}
@@ -2,18 +2,38 @@ mod game;
 mod types;
 mod api;
 mod card_classification;
-
-use std::collections::HashMap;
+mod collection;
+mod simulation;
+mod draft;
+mod trade;
+mod rules;
+mod solver;
+mod ismcts;
+mod search;
+mod tablebase;
+mod book;
+mod saved;
+
+use std::{collections::HashMap, io::Write, sync::Arc, time::Duration};
 use card_classification::explore_cardlist;
 use colored::Colorize;
 use game::*;
+use itertools::Itertools;
 
 use types::*;
-use mcts::{MCTSManager, tree_policy::UCTPolicy, transposition_table::ApproxTable};
+use rand::{SeedableRng, StdRng};
+
+const DETERMINIZATIONS: usize = 10;
+const DETERMINIZATION_SEED: usize = 0;
+const DEFAULT_THINK_TIME: Duration = Duration::from_secs(3);
+const SEARCH_THREADS: usize = 4;
+const SIMULATION_PLAYOUTS: u64 = 10_000;
+const DRAFT_ROLLOUTS: usize = 8;
+const DRAFT_PLAYOUTS: u64 = 1_000;
 use crate::{game::{Player, Move}};
 
 
-fn prompt_for_move(game:&Game) -> Move {
+fn prompt_for_move(game:&mut Game) -> Move {
   let mut line = String::new();
   let prompt_text = "Please enter your next move:";
   let prompt = match game.turn {Player::Blue => prompt_text.blue(), Player::Red => prompt_text.red()};
@@ -22,8 +42,7 @@ fn prompt_for_move(game:&Game) -> Move {
   let response = match std::io::stdin().read_line(&mut line) {
     Ok(_response_size) => {
       let inputs = line.trim().split(" ").collect::<Vec<&str>>();
-      let hand = &game.hands[&game.turn];
-      match (valid_hand_idx(&hand, inputs[0]), inputs[1].parse::<Coord>()) {
+      match (valid_hand_idx(game, inputs[0]), inputs.get(1).unwrap_or(&"").parse::<Coord>()) {
         (Ok(card_idx), Ok(coords)) => Ok((card_idx, coords)),
         (Ok(_), Err(_)) => Err("Encountered parse int error when reading coordinates".to_string()),
         (Err(e), Ok(_)) => Err(e),
@@ -35,7 +54,13 @@ fn prompt_for_move(game:&Game) -> Move {
 
   match response {
     Ok((card_idx, coords)) => {
-      let card = game.hands[&game.turn].0[card_idx].0.clone().unwrap();
+      // The opponent is playing a card we couldn't see; find out what it was
+      if game.hands[&game.turn].0[card_idx].is_hidden() {
+        let revealed_card = prompt_for_revealed_card();
+        let player = game.turn;
+        game.reveal_card(revealed_card, player, card_idx);
+      }
+      let card = game.hands[&game.turn].0[card_idx].card().cloned().unwrap();
       let entered_move = Move::new(card, coords, game.turn);
       let mut test_game = game.clone();
       match test_game.make_move(&entered_move) {
@@ -51,6 +76,27 @@ fn prompt_for_move(game:&Game) -> Move {
   }
 }
 
+fn prompt_for_forced_card(game:&mut Game) {
+  let mut line = String::new();
+  println!("Which card did Chaos select? Enter its position in your hand");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) => {
+      match line.trim().parse::<usize>().ok().and_then(|idx| game.force_card(idx)) {
+        Some(_game) => (),
+        None => {
+          println!("There is no card in that position, try again");
+          prompt_for_forced_card(game)
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_forced_card(game)
+    }
+  }
+}
+
 fn prompt_for_your_color() -> Player {
   let mut line = String::new();
   println!("What color are you?");
@@ -97,40 +143,401 @@ fn prompt_for_first_player() -> Player {
   }
 }
 
-fn prompt_for_card() -> GameCard {
+// A card entered as "top right bottom left [element]", looked up in the cardlist by its sides for its name,
+// stars and tribe. If no card or several match, it's left unidentified with no stars, so it counts for nothing
+// when trades are weighed.
+fn parse_card(s:&str, cardlist:&[Card]) -> Option<GameCard> {
+  let inputs = s.trim().split(" ").collect::<Vec<&str>>();
+  let valid_inputs = inputs.iter().filter_map(|x| parse_number(x)).collect::<Vec<usize>>();
+  let element = inputs.iter().find_map(|x| x.parse::<Element>().ok());
+  match valid_inputs[..] {
+    [top, right, bottom, left] => {
+      let entered = Card { name: "Unidentified".to_string(), id: 0, stars: 0, stats: CardStats { top, right, bottom, left, tribe: None }, element };
+      let card = match collection::identify_card(&entered, cardlist) {
+        Ok(known) => Card { element: element.or(known.element), ..known },
+        Err(_) => entered
+      };
+      Some(GameCard{ card, player:Some(Player::Red), owner:None})
+    }
+    _ => None
+  }
+}
+
+// The cards a hidden slot could hold: a deck file, a star tier (1-5), or cards separated by ";"
+fn parse_candidates(s:&str) -> Result<Vec<GameCard>, String> {
+  let as_game_cards = |cards:Vec<Card>| cards.into_iter().map(|card| GameCard { card, player: None, owner: None }).collect::<Vec<GameCard>>();
+  let cardlist = api::read_cardlist();
+  let candidates = match s.trim().parse::<usize>() {
+    Ok(stars) if (1..=5).contains(&stars) => as_game_cards(ismcts::star_tier_pool(&cardlist, stars)),
+    Ok(_) => return Err("Star tiers run from 1 to 5".to_string()),
+    Err(_) if s.contains(';') => s.split(';').map(|card| parse_card(card, &cardlist)).collect::<Option<Vec<GameCard>>>().ok_or("Encountered error when parsing a candidate card".to_string())?,
+    Err(_) => as_game_cards(collection::Collection::load(s.trim())?.cards)
+  };
+  match candidates.is_empty() {
+    true => Err("There must be at least one candidate".to_string()),
+    false => Ok(candidates)
+  }
+}
+
+fn prompt_for_card() -> HandSlot {
+  let mut line = String::new();
+  println!("Enter the next card, \"top right bottom left [element]\", \"unknown\" if it is hidden, or \"from <deck file | star tier | card; card; ...>\" if it is one of several");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().to_lowercase() == "unknown" => HandSlot::Unknown,
+    Ok(_response_size) if line.trim().starts_with("from ") => {
+      match parse_candidates(line.trim().trim_start_matches("from ")) {
+        Ok(candidates) => HandSlot::Candidates(candidates),
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_card()
+        }
+      }
+    }
+    Ok(_response_size) => {
+      match parse_card(&line, &api::read_cardlist()) {
+        Some(card) => HandSlot::Known(card),
+        None => {
+          println!("Encountered error when parsing numbers. Use format \"# # # #\", with 1-9 or A");
+          prompt_for_card()
+        }
+      }
+    }
+    _ => {println!("Encountered unknown error when parsing input"); prompt_for_card()}
+  }
+}
+
+// A closed hand's slot: any card, or one of a few candidates if we know that much
+fn prompt_for_hidden_slot() -> HandSlot {
+  let mut line = String::new();
+  println!("Enter where the hidden card could come from, \"from <deck file | star tier | card; card; ...>\", or nothing if it could be any card");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => HandSlot::Unknown,
+    Ok(_response_size) => {
+      let candidates = line.trim().strip_prefix("from ").ok_or("Start the list with \"from\"".to_string()).and_then(parse_candidates);
+      match candidates {
+        Ok(candidates) => HandSlot::Candidates(candidates),
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_hidden_slot()
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_hidden_slot()
+    }
+  }
+}
+
+fn prompt_for_revealed_card() -> GameCard {
+  println!("That card was hidden; enter the card that was revealed");
+  match prompt_for_card() {
+    HandSlot::Known(card) => card,
+    _ => prompt_for_revealed_card()
+  }
+}
+
+fn prompt_for_hand_idx(prompt:&str, hand_size:usize) -> usize {
   let mut line = String::new();
-  println!("Enter the first opponent's card, \"top right bottom left\" ");
+  println!("{}", prompt);
 
   match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) => {
+      match line.trim().parse::<usize>() {
+        Ok(idx) if idx < hand_size => idx,
+        _ => {
+          println!("Please enter a number between 0 and {}", hand_size - 1);
+          prompt_for_hand_idx(prompt, hand_size)
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_hand_idx(prompt, hand_size)
+    }
+  }
+}
+
+// House rules and puzzles may use a bigger board; anything but 3x3 has to be asked for
+// Past this, hands get too big for the search to say anything useful in the time it has
+const BOARD_SIDES: std::ops::RangeInclusive<usize> = 2..=6;
+
+fn prompt_for_board_size() -> Board {
+  let mut line = String::new();
+  println!("Enter the board size as \"rows x columns\", or press enter for 3x3");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => Board::default(),
+    Ok(_response_size) => {
+      let dimensions = line.trim().to_lowercase().split('x').map(|x| x.trim().parse::<usize>().ok()).collect::<Vec<Option<usize>>>();
+      match dimensions[..] {
+        [Some(rows), Some(cols)] if BOARD_SIDES.contains(&rows) && BOARD_SIDES.contains(&cols) => Board::new(rows, cols),
+        [Some(_), Some(_)] => {
+          println!("Boards run from {0}x{0} to {1}x{1}, try again", BOARD_SIDES.start(), BOARD_SIDES.end());
+          prompt_for_board_size()
+        }
+        _ => {
+          println!("Use format \"rows x columns\", e.g. \"4x4\"");
+          prompt_for_board_size()
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_board_size()
+    }
+  }
+}
+
+// Swap exchanges one random card between the hands before the first move
+fn prompt_for_swap(game:&mut Game) {
+  let my_color = game.my_color;
+  let opponent_color = my_color.other();
+  let my_idx = prompt_for_hand_idx("Swap: which position in your hand was swapped out?", game.hand_size());
+  let opponent_idx = prompt_for_hand_idx("Swap: which position in the opponent's hand did your new card come from?", game.hand_size());
+  let mut received_card = match game.hands[&opponent_color].0[opponent_idx].card() {
+    Some(card) => card.clone(),
+    None => {
+      println!("Swap: enter the card you received");
+      prompt_for_revealed_card()
+    }
+  };
+  received_card.owner = Some(opponent_color);
+  game.reveal_card(received_card, opponent_color, opponent_idx);
+  match my_color {
+    Player::Blue => game.swap_cards(my_idx, opponent_idx),
+    Player::Red => game.swap_cards(opponent_idx, my_idx)
+  };
+}
+
+// Elemental squares are rolled in-game; read them one at a time until a blank line
+fn prompt_for_elements(game:&mut Game) {
+  let mut line = String::new();
+  println!("Enter an elemental square as \"row,col element\", or press enter when done");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => (),
     Ok(_response_size) => {
       let inputs = line.trim().split(" ").collect::<Vec<&str>>();
-      let valid_inputs = inputs.iter().map(|x| x.parse::<usize>()).filter_map((|x| match x {Ok(x) if (1..=10).contains(&x) => Some(x), _ => None})).collect::<Vec<usize>>();
-      if valid_inputs.len() == 4 {
-        let top = valid_inputs[0];
-        let right = valid_inputs[1];
-        let bottom = valid_inputs[2];
-        let left = valid_inputs[3];
-        let card = GameCard{ card:Card{name:"Doesn't_Matter".to_string(), id:0, stars:3, stats: CardStats { top:top, right:right, bottom:bottom, left:left, tribe:None}}, player:Some(Player::Red)};
-        card
+      match (inputs.first().and_then(|x| x.parse::<Coord>().ok()), inputs.get(1).map(|x| x.parse::<Element>())) {
+        (Some(coords), Some(Ok(element))) => {
+          if game.set_square_element(&coords, Some(element)).is_none() {
+            println!("{},{} isn't on the board, try again", coords.row + 1, coords.col + 1);
+          }
+        }
+        (_, Some(Err(e))) => println!("{}, try again", e),
+        _ => println!("Use format \"row,col element\", e.g. \"2,2 fire\"")
       }
-      else {
-        println!("Encountered error when parsing numbers. Use format \"# # # #\" ");
-        prompt_for_card()
+      prompt_for_elements(game)
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_elements(game)
+    }
+  }
+}
+
+// Our cards that weren't entered with an element, one at a time; a blank line means the card has none
+fn prompt_for_hand_elements(game:&mut Game, player:Player) {
+  let unset_idxs = game.hands[&player].0.iter().positions(|slot| slot.card().is_some_and(|card| card.card.element.is_none())).collect::<Vec<usize>>();
+  for idx in unset_idxs {
+    let element = prompt_for_card_element(game.hands[&player].0[idx].card().unwrap());
+    if let HandSlot::Known(card) = &mut game.hands.get_mut(&player).unwrap().0[idx] {
+      card.card.element = element;
+    }
+  }
+}
+
+fn prompt_for_card_element(card:&GameCard) -> Option<Element> {
+  let mut line = String::new();
+  println!("Enter the element of {}, or press enter if it has none", card.card.name);
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => None,
+    Ok(_response_size) => {
+      match line.parse::<Element>() {
+        Ok(element) => Some(element),
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_card_element(card)
+        }
       }
     }
-    _ => {println!("Encountered unknown error when parsing input"); prompt_for_card()}
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_card_element(card)
+    }
+  }
+}
+
+fn prompt_for_rules() -> RuleSet {
+  let mut line = String::new();
+  println!("Enter the rules for this game, separated by commas (e.g. \"all open, plus, fallen ace\")");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) => {
+      match line.parse::<RuleSet>() {
+        Ok(rules) if rules.contains(Rule::Roulette) => prompt_for_roulette(rules),
+        Ok(rules) => rules,
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_rules()
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_rules()
+    }
+  }
+}
+
+// Roulette is rolled in-game, so swap it for whatever the game actually rolled
+fn prompt_for_roulette(rules:RuleSet) -> RuleSet {
+  let mut line = String::new();
+  println!("Roulette: enter the rules that were rolled, separated by commas");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) => {
+      match line.parse::<RuleSet>() {
+        Ok(rolled) if rolled.is_empty() || rolled.contains(Rule::Roulette) => {
+          println!("Enter the concrete rules Roulette rolled, try again");
+          prompt_for_roulette(rules)
+        }
+        Ok(rolled) => {
+          match rules.without(Rule::Roulette).iter().chain(rolled.iter()).collect::<RuleSet>().validate() {
+            Ok(rules) => rules,
+            Err(e) => {
+              println!("{}, try again", e);
+              prompt_for_roulette(rules)
+            }
+          }
+        }
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_roulette(rules)
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_roulette(rules)
+    }
+  }
+}
+
+fn prompt_for_house_rules() -> rules::HouseRules {
+  let mut line = String::new();
+  println!("Enter any house rules, separated by commas (corner bonus), or nothing for none");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) => {
+      let house_rules = 
+        line
+        .split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(rules::house_rule)
+        .collect::<Result<Vec<_>, String>>();
+      match house_rules {
+        Ok(house_rules) => rules::HouseRules(house_rules),
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_house_rules()
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_house_rules()
+    }
+  }
+}
+
+fn prompt_for_trade_rule() -> Option<trade::TradeRule> {
+  let mut line = String::new();
+  println!("Enter the trade rule (one, diff, direct, all), or nothing if no cards are traded");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => None,
+    Ok(_response_size) => {
+      match line.parse::<trade::TradeRule>() {
+        Ok(rule) => Some(rule),
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_trade_rule()
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_trade_rule()
+    }
+  }
+}
+
+fn prompt_for_objective() -> Objective {
+  let mut line = String::new();
+  println!("What should the AI play for? (win, margin, safe), or nothing to maximize winning chances");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => Objective::default(),
+    Ok(_response_size) => {
+      match line.parse::<Objective>() {
+        Ok(objective) => objective,
+        Err(e) => {
+          println!("{}, try again", e);
+          prompt_for_objective()
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_objective()
+    }
+  }
+}
+
+// The cards the opponent's hidden cards are drawn from: their deck if we know it, a star tier, or any card
+fn prompt_for_candidate_pool() -> Vec<Card> {
+  let mut line = String::new();
+  println!("Where could the opponent's hidden cards come from? Enter their deck file, a star tier (1-5), or nothing for any card");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => api::read_cardlist(),
+    Ok(_response_size) => {
+      match line.trim().parse::<usize>() {
+        Ok(stars) if (1..=5).contains(&stars) => ismcts::star_tier_pool(&api::read_cardlist(), stars),
+        Ok(_) => {
+          println!("Star tiers run from 1 to 5, try again");
+          prompt_for_candidate_pool()
+        }
+        Err(_) => match collection::Collection::load(line.trim()) {
+          Ok(deck) => deck.cards,
+          Err(e) => {
+            println!("{}, try again", e);
+            prompt_for_candidate_pool()
+          }
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_candidate_pool()
+    }
   }
 }
 
 fn build_my_hand(game:&mut Game, player:Player) -> &mut Game{
 
   let cards = HashMap::from(
-    [ ("Hildi", GameCard{ card: Card { name: "Hildibrand & Nashu Mhakaracca".to_string(), id: 0, stars: 5, stats: CardStats {  top:1, right:8, bottom:10, left:8, tribe:None}}, player:None})
-    , ("Roundrox", GameCard{ card: Card { name: "Roundrox".to_string(), id: 0, stars: 3, stats: CardStats { top: 2, right:2, bottom:8, left: 8, tribe:Some(Tribe::Beastman) }}, player:None})
-    , ("Estinien", GameCard{ card: Card { name: "Estinien".to_string(), id: 0, stars: 3, stats: CardStats { top: 8, right:8, bottom:2, left: 3, tribe:None}}, player:None})
-    , ("Alphinaud and Alisae", GameCard{ card: Card { name: "Alphinaud and Alisae".to_string(), id: 0, stars: 4, stats: CardStats { top: 9, right:3, bottom:3, left: 9, tribe:None}}, player:None})
-    , ("Ysayle", GameCard{ card: Card { name: "Ysayle".to_string(), id: 0, stars: 3, stats: CardStats { top: 4, right:8, bottom:8, left: 1, tribe:None}}, player:None})
-    , ("Therion", GameCard{ card: Card { name: "Thereon".to_string(), id: 0, stars: 5, stats: CardStats { top: 9, right:9, bottom:2, left: 9, tribe:None}}, player:None})
+    [ ("Hildi", GameCard{ card: Card { name: "Hildibrand & Nashu Mhakaracca".to_string(), id: 0, stars: 5, stats: CardStats {  top:1, right:8, bottom:10, left:8, tribe:None}, element:None}, player:None, owner:None})
+    , ("Roundrox", GameCard{ card: Card { name: "Roundrox".to_string(), id: 0, stars: 3, stats: CardStats { top: 2, right:2, bottom:8, left: 8, tribe:Some(Tribe::Beastman) }, element:None}, player:None, owner:None})
+    , ("Estinien", GameCard{ card: Card { name: "Estinien".to_string(), id: 0, stars: 3, stats: CardStats { top: 8, right:8, bottom:2, left: 3, tribe:None}, element:None}, player:None, owner:None})
+    , ("Alphinaud and Alisae", GameCard{ card: Card { name: "Alphinaud and Alisae".to_string(), id: 0, stars: 4, stats: CardStats { top: 9, right:3, bottom:3, left: 9, tribe:None}, element:None}, player:None, owner:None})
+    , ("Ysayle", GameCard{ card: Card { name: "Ysayle".to_string(), id: 0, stars: 3, stats: CardStats { top: 4, right:8, bottom:8, left: 1, tribe:None}, element:None}, player:None, owner:None})
+    , ("Therion", GameCard{ card: Card { name: "Thereon".to_string(), id: 0, stars: 5, stats: CardStats { top: 9, right:9, bottom:2, left: 9, tribe:None}, element:None}, player:None, owner:None})
     ]
   );  
 
@@ -146,79 +553,457 @@ fn build_my_hand(game:&mut Game, player:Player) -> &mut Game{
 fn initialize_game() -> Game {
   let player_color = prompt_for_your_color();
   let first_player = prompt_for_first_player();
-  let empty_hand : [Square; 5] = [Square(None), Square(None), Square(None), Square(None), Square(None)];
-  let mut game = Game
-    { turn: first_player
-    , hands: HashMap::from(
-      [ (Player::Red, Hand(empty_hand.clone())),
-        (Player::Blue, Hand(empty_hand.clone()))
-      ]
-    )
-    , board: Board([[Square(None), Square(None), Square(None)], [Square(None), Square(None), Square(None)], [Square(None), Square(None), Square(None)]])
-    , first_player: first_player
-    , my_color: player_color
-    , rules: vec![Rule::Plus, Rule::AllOpen]
+  let rules = prompt_for_rules();
+  let board = prompt_for_board_size();
+  let mut game = Game::with_board(board, first_player, player_color, rules);
+  game.house_rules = prompt_for_house_rules();
+  let hand_size = game.hand_size();
+  let mut game = 
+    if game.rules.contains(Rule::Random) || game.rules.contains(Rule::Draft) { &mut game }
+    else { build_my_hand(&mut game, player_color) };
+  // Our hand was dealt or drafted in-game, or has more cards than build_my_hand provides, so enter the rest
+  let missing_cards = hand_size - game.hands[&player_color].occupied_idxs().len();
+  if missing_cards > 0 {
+    println!("Enter the cards in your hand");
+  }
+  for _ in 0..missing_cards {
+    let card = prompt_for_revealed_card();
+    game.add_card_to_hand(card, player_color);
+  }
+  let opponent_color = player_color.other();
+  let hand_is_open = game.rules.contains(Rule::AllOpen) || game.rules.contains(Rule::ThreeOpen);
+  println!("Enter the opponent's cards");
+  for _ in 0..hand_size {
+    let slot = if hand_is_open { prompt_for_card() } else { prompt_for_hidden_slot() };
+    game = match slot {
+      HandSlot::Known(card) => game.add_card_to_hand(card, opponent_color),
+      slot => game.add_slot_to_hand(slot, opponent_color)
     };
-  let mut game = build_my_hand(&mut game, player_color);
-  for i in (0..=4) {
-    let card = prompt_for_card();
-    game = game.add_card_to_hand(card, match player_color {Player::Red => Player::Blue, Player::Blue => Player::Red});
   }
+  if game.rules.contains(Rule::Swap) {
+    prompt_for_swap(game);
+  }
+  if game.rules.contains(Rule::Elemental) {
+    prompt_for_hand_elements(game, player_color);
+    prompt_for_elements(game);
+  }
+  game.trade_rule = prompt_for_trade_rule();
   game.clone()
 }
 
 
 
-fn play_game(game: &mut Game) {
-  loop {
+// How a move is entered at the prompt: the hand position and the one-based square
+fn describe_move(game: &Game, user_move: &Move) -> String {
+  let hand_idx = game.hands[&user_move.player].0.iter().position(|slot| slot.card() == Some(&user_move.card)).unwrap_or(0);
+  format!("Card {} to {},{}", hand_idx, user_move.coords.row + 1, user_move.coords.col + 1)
+}
+
+// With hidden cards, search several determinizations of the opponent's hand and combine their statistics
+fn evaluate_hidden_moves(game: &Game, settings:&SearchSettings) {
+  let mut rng = StdRng::from_seed(&[DETERMINIZATION_SEED][..]);
+  let time_per_sample = settings.think_time / DETERMINIZATIONS as u32;
+  let stats = ismcts::determinized_search(game, &settings.pool, &mut rng, DETERMINIZATIONS, time_per_sample, SEARCH_THREADS, &settings.evaluator());
+  let total_visits = stats.iter().map(|(_, stats)| stats.visits).sum::<u64>().max(1);
+
+  for (action, stats) in stats {
+    if let Action::Play(user_move) = action {
+      println!("{}: {:.1}% of visits, value {:.2}", describe_move(game, &user_move), 100.0 * stats.visits as f32 / total_visits as f32, stats.mean_value());
+    }
+  }
+}
 
-    let copied_game = game.clone();
-    
+// Small enough to search exhaustively, so report the exact outcome of every move
+fn solve_moves(game: &Game, settings:&SearchSettings) {
+  let mut solver = solver::Solver::with_tablebase(settings.tablebase.clone());
+  for outcome in solver.solve_moves(game) {
+    println!("{}: {}", describe_move(game, &outcome.user_move), outcome);
+  }
+  println!("Solved exactly ({} positions)", solver.nodes);
+}
+
+// How the AI searches on our turns
+struct SearchSettings
+{ objective: Objective
+// The cards the opponent's hidden cards could be
+, pool: Vec<Card>
+, think_time: Duration
+// Exact endgame results, shared with the evaluator and the solver
+, tablebase: Arc<tablebase::Tablebase>
+, book: book::OpeningBook
+}
+impl SearchSettings {
+  fn evaluator(&self) -> MyEvaluator {
+    MyEvaluator::with_tablebase(self.objective, self.tablebase.clone())
+  }
+}
+
+// Searches for the think time, keeping a progress line updated as it goes
+fn evaluate_moves(game: &Game, settings:&SearchSettings) {
+  let mcts = search::timed_search(game, settings.evaluator(), settings.think_time, SEARCH_THREADS, |progress| {
+    print!("\r{}    ", progress);
+    std::io::stdout().flush().ok();
+  });
+  println!();
+  mcts.tree().debug_moves();
+}
+
+// Starts from an empty tablebase if the saved one can't be read
+fn load_tablebase() -> Arc<tablebase::Tablebase> {
+  let tablebase = tablebase::Tablebase::load(tablebase::TABLEBASE_FILENAME).unwrap_or_else(|e| {
+    println!("{}", e);
+    tablebase::Tablebase::new()
+  });
+  println!("Loaded {} endgame positions", tablebase.positions());
+  Arc::new(tablebase)
+}
+
+fn load_book() -> book::OpeningBook {
+  let book = book::OpeningBook::load(book::BOOK_FILENAME).unwrap_or_else(|e| {
+    println!("{}", e);
+    book::OpeningBook::new()
+  });
+  println!("Loaded {} opening positions", book.positions());
+  book
+}
+
+fn play_game(game: &mut Game, settings:&SearchSettings) {
+  loop {
 
     println!("{}", game);
 
     if game.turn == game.my_color {
-      println!("Evaluating Moves...");
-      let mut mcts = MCTSManager::new(copied_game, MyMCTS, MyEvaluator, UCTPolicy::new(0.5), ApproxTable::new(1024));
-      mcts.playout_n_parallel(250_000, 4);
-      mcts.tree().debug_moves();
+      if game.awaiting_chaos() {
+        prompt_for_forced_card(game);
+      }
+      match settings.book.lookup(game, &settings.pool) {
+        Some(user_move) => println!("Opening book: {}", describe_move(game, &user_move)),
+        None => {
+          println!("Evaluating Moves...");
+          if solver::can_solve(game) {
+            solve_moves(game, settings);
+          }
+          else if game.has_hidden_cards() {
+            evaluate_hidden_moves(game, settings);
+          }
+          else {
+            evaluate_moves(game, settings);
+          }
+        }
+      }
     }
     let next_move = prompt_for_move(game);
-    let result = game.make_move(&next_move);
-    if result.is_none() {
-      println!("Game Over!");
-      break
+    match game.make_move(&next_move) {
+      Some(MoveResult::NextRound) => {
+        println!("{}", format!("Draw! Sudden Death begins round {}", game.round).bold());
+        let first_player = prompt_for_first_player();
+        game.first_player = first_player;
+        game.turn = first_player;
+      }
+      Some(MoveResult::Finished(result)) => {
+        println!("{}", game);
+        match result {
+          GameResult::Draw => println!("Game Over! The game is a draw"),
+          GameResult::Win(player) if player == game.my_color => println!("Game Over! You win"),
+          GameResult::Win(_) => println!("Game Over! You lose"),
+        }
+        if let Some(rule) = game.trade_rule {
+          settle_trade(game, rule, &result);
+        }
+        if let Err(e) = settings.tablebase.save(tablebase::TABLEBASE_FILENAME) {
+          println!("{}", e);
+        }
+        break
+      }
+      _ => ()
     }
   }
 }
 
 
 
-fn main() -> (){
+// Which of the loser's cards the winner took; the suggestion assumes they took the most valuable ones
+fn prompt_for_trade_picks(stakes:&[Card], n_picks:usize) -> Vec<usize> {
+  let mut line = String::new();
+  for (idx, card) in stakes.iter().enumerate() {
+    println!("{}: {} ({}*)", idx, card.name, card.stars);
+  }
+  println!("Enter the {} card(s) taken, separated by spaces, or nothing for {:?}", n_picks, (0..n_picks).collect::<Vec<usize>>());
 
-  let cards = HashMap::from(
-    [ ("Hildi", GameCard{ card: Card { name: "Hildibrand & Nashu Mhakaracca".to_string(), id: 0, stars: 5, stats: CardStats {  top:1, right:8, bottom:10, left:8, tribe:None}}, player:None})
-    , ("Roundrox", GameCard{ card: Card { name: "Roundrox".to_string(), id: 0, stars: 3, stats: CardStats { top: 2, right:2, bottom:8, left: 8, tribe:Some(Tribe::Beastman) }}, player:None})
-    , ("Estinien", GameCard{ card: Card { name: "Estinien".to_string(), id: 0, stars: 3, stats: CardStats { top: 8, right:8, bottom:2, left: 3, tribe:None}}, player:None})
-    , ("Alphinaud and Alisae", GameCard{ card: Card { name: "Alphinaud and Alisae".to_string(), id: 0, stars: 4, stats: CardStats { top: 9, right:3, bottom:3, left: 9, tribe:None}}, player:None})
-    , ("Ysayle", GameCard{ card: Card { name: "Ysayle".to_string(), id: 0, stars: 3, stats: CardStats { top: 4, right:8, bottom:8, left: 1, tribe:None}}, player:None})
-    , ("Therion", GameCard{ card: Card { name: "Thereon".to_string(), id: 0, stars: 5, stats: CardStats { top: 9, right:9, bottom:2, left: 9, tribe:None}}, player:None})
-    ]
-  );  
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => (0..n_picks).collect(),
+    Ok(_response_size) => {
+      let picks = line.split_whitespace().map(|x| x.parse::<usize>().ok().filter(|idx| *idx < stakes.len())).collect::<Option<Vec<usize>>>();
+      match picks {
+        Some(picks) if picks.len() == n_picks && picks.iter().all_unique() => picks,
+        _ => {
+          println!("Please enter {} different numbers between 0 and {}", n_picks, stakes.len() - 1);
+          prompt_for_trade_picks(stakes, n_picks)
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_trade_picks(stakes, n_picks)
+    }
+  }
+}
 
-  let mut game = initialize_game();
+fn prompt_for_collection_file() -> Option<String> {
+  let mut line = String::new();
+  println!("Enter your collection file to record the trade in, or nothing to skip");
 
-  let game = game.add_card_to_hand(cards["Hildi"].clone(), Player::Red);
-  let game = game.add_card_to_hand(cards["Roundrox"].clone(), Player::Red);
-  let game = game.add_card_to_hand(cards["Estinien"].clone(), Player::Red);
-  let game = game.add_card_to_hand(cards["Alphinaud and Alisae"].clone(), Player::Red);
-  let game = game.add_card_to_hand(cards["Ysayle"].clone(), Player::Red);
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) if line.trim().is_empty() => None,
+    Ok(_response_size) => Some(line.trim().to_string()),
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_collection_file()
+    }
+  }
+}
 
-  let game = game.add_card_to_hand(cards["Hildi"].clone(), Player::Blue);
-  let game = game.add_card_to_hand(cards["Roundrox"].clone(), Player::Blue);
-  let game = game.add_card_to_hand(cards["Estinien"].clone(), Player::Blue);
-  let game = game.add_card_to_hand(cards["Alphinaud and Alisae"].clone(), Player::Blue);
-  let game = game.add_card_to_hand(cards["Ysayle"].clone(), Player::Blue);
+// The post-game phase: work out which cards change hands and record our side of it
+fn settle_trade(game:&Game, rule:trade::TradeRule, result:&GameResult) {
+  let trade = match (rule, result) {
+    (trade::TradeRule::One | trade::TradeRule::Diff, GameResult::Win(winner)) => {
+      let stakes = trade::stakes(game, winner.other());
+      let n_picks = trade::cards_to_pick(game, rule, result);
+      let picks = if n_picks > 0 { prompt_for_trade_picks(&stakes, n_picks) } else { Vec::new() };
+      trade::Trade::taking(picks.into_iter().map(|idx| stakes[idx].clone()).collect(), winner.other(), *winner)
+    }
+    _ => trade::compute_trade(game, rule, result)
+  };
+  println!("{:?} trade:\n{}", rule, trade);
+  if trade.0.is_empty() {
+    return
+  }
+
+  if let Some(filename) = prompt_for_collection_file() {
+    let updated = collection::Collection::load(&filename).and_then(|mut collection| {
+      collection.apply_trade(&trade, game.my_color, &api::read_cardlist())?;
+      collection.save(&filename)
+    });
+    match updated {
+      Ok(()) => println!("Updated {}", filename),
+      Err(e) => println!("{}", e)
+    }
+  }
+}
+
+
+
+// Estimates our win rate as Blue when both hands are dealt at random from collection files
+fn run_win_rate(args:&[String]) {
+  let (my_filename, opponent_filename) = match args {
+    [mine, theirs, ..] => (mine, theirs),
+    _ => {
+      println!("Usage: winrate <my_collection.json> <opponent_collection.json> [games] [seed] [rules]");
+      return
+    }
+  };
+  let games = args.get(2).and_then(|x| x.parse::<usize>().ok()).unwrap_or(100);
+  let seed = args.get(3).and_then(|x| x.parse::<usize>().ok()).unwrap_or(0);
+  let rules = match args.get(4).map(|x| x.as_str()).unwrap_or("").parse::<RuleSet>().and_then(|rules| rules.with(Rule::Random).validate()) {
+    Ok(rules) => rules,
+    Err(e) => { println!("{}", e); return }
+  };
+  let collections = match (collection::Collection::load(my_filename), collection::Collection::load(opponent_filename)) {
+    (Ok(mine), Ok(theirs)) => HashMap::from([(Player::Blue, mine), (Player::Red, theirs)]),
+    (Err(e), _) | (_, Err(e)) => { println!("{}", e); return }
+  };
+
+  let (win_rate, deals) = match simulation::estimate_win_rate(&collections, Player::Blue, rules, &ROULETTE_POOL, games, seed, SIMULATION_PLAYOUTS) {
+    Ok(estimate) => estimate,
+    Err(e) => { println!("{}", e); return }
+  };
+  for deal in deals {
+    println!("{}", deal);
+  }
+  println!("{}", win_rate);
+}
+
+// The move the AI would play, with its mean value, for building the opening book
+fn book_search(game:&Game, pool:&[Card], evaluator:&MyEvaluator, think_time:Duration) -> Option<(Move, f32)> {
+  let stats = if game.has_hidden_cards() {
+    let mut rng = StdRng::from_seed(&[DETERMINIZATION_SEED][..]);
+    ismcts::determinized_search(game, pool, &mut rng, DETERMINIZATIONS, think_time / DETERMINIZATIONS as u32, SEARCH_THREADS, evaluator)
+  }
+  else {
+    let mcts = search::timed_search(game, evaluator.clone(), think_time, SEARCH_THREADS, |_| ());
+    mcts.tree().root_node().moves()
+      .map(|info| (info.get_move().clone(), ismcts::MoveStats { visits: info.visits(), sum_rewards: info.sum_rewards() }))
+      .sorted_by_key(|(_, stats)| std::cmp::Reverse(stats.visits))
+      .collect()
+  };
+  stats.into_iter().find_map(|(action, stats)| match action {
+    Action::Play(user_move) => Some((user_move, stats.mean_value())),
+    Action::Force(_) => None
+  })
+}
+
+// Builds or extends the opening book for our deck, as Blue, against one opponent deck, with either player going first.
+// The opponent's hand is hidden unless All Open shows it.
+fn run_book(args:&[String]) {
+  let (my_filename, opponent_filename) = match args {
+    [mine, theirs, ..] => (mine, theirs),
+    _ => {
+      println!("Usage: book <my_deck.json> <opponent_deck.json> [think_seconds] [my_moves] [rules]");
+      return
+    }
+  };
+  let Some(think_time) = parse_think_time(args.get(2).map(|x| x.as_str())) else {
+    println!("Usage: book <my_deck.json> <opponent_deck.json> [think_seconds] [my_moves] [rules]");
+    return
+  };
+  let my_moves = args.get(3).and_then(|x| x.parse::<usize>().ok()).unwrap_or(1);
+  let rules = match args.get(4).map(|x| x.as_str()).unwrap_or("").parse::<RuleSet>().and_then(|rules| rules.validate()) {
+    Ok(rules) => rules,
+    Err(e) => { println!("{}", e); return }
+  };
+  if let Some(rule) = book::UNBOOKABLE_RULES.iter().find(|rule| rules.contains(**rule)) {
+    println!("Can't build an opening book under {:?}, since the opening isn't known in advance", rule);
+    return
+  }
+  let (my_deck, opponent_deck) = match (collection::Collection::load(my_filename), collection::Collection::load(opponent_filename)) {
+    (Ok(mine), Ok(theirs)) => (mine.cards, theirs.cards),
+    (Err(e), _) | (_, Err(e)) => { println!("{}", e); return }
+  };
+  let hand_size = Game::new(Player::Blue, Player::Blue, rules).hand_size();
+  let hand_is_open = rules.contains(Rule::AllOpen);
+  if my_deck.len() != hand_size || (hand_is_open && opponent_deck.len() != hand_size) {
+    println!("Decks must hold exactly {} cards{}", hand_size, if hand_is_open { "" } else { " (the opponent's can hold more)" });
+    return
+  }
+  // The pool is only part of the key while the opponent's hand is hidden, as in play
+  let pool = if hand_is_open { Vec::new() } else { opponent_deck.clone() };
+
+  let tablebase = load_tablebase();
+  let mut opening_book = load_book();
+  let evaluator = MyEvaluator::with_tablebase(Objective::default(), tablebase.clone());
+  for first_player in [Player::Blue, Player::Red] {
+    let mut game = Game::new(first_player, Player::Blue, rules);
+    for card in &my_deck {
+      game.add_card_to_hand(GameCard { card: card.clone(), player: None, owner: None }, Player::Blue);
+    }
+    for card in &opponent_deck[..hand_size] {
+      match hand_is_open {
+        true => game.add_card_to_hand(GameCard { card: card.clone(), player: None, owner: None }, Player::Red),
+        false => game.add_slot_to_hand(HandSlot::Unknown, Player::Red)
+      };
+    }
+
+    let added = opening_book.extend(&game, &pool, my_moves, &mut |position| {
+      let best = book_search(position, &pool, &evaluator, think_time);
+      if let Some((user_move, value)) = &best {
+        let placed = position.board.coords().count() - position.open_squares().len();
+        println!("{} first, {} cards placed: {} (value {:.2})", first_player, placed, describe_move(position, user_move), value);
+      }
+      best
+    });
+    println!("Added {} positions with {} going first", added, first_player);
+  }
+
+  if let Err(e) = opening_book.save(book::BOOK_FILENAME) {
+    println!("{}", e);
+  }
+  if let Err(e) = tablebase.save(tablebase::TABLEBASE_FILENAME) {
+    println!("{}", e);
+  }
+}
+
+fn prompt_for_pick(n_cards:usize) -> usize {
+  let mut line = String::new();
+  println!("Which card do you pick?");
+
+  match std::io::stdin().read_line(&mut line) {
+    Ok(_response_size) => {
+      match line.trim().parse::<usize>() {
+        Ok(idx) if idx < n_cards => idx,
+        _ => {
+          println!("Please enter a number between 0 and {}", n_cards - 1);
+          prompt_for_pick(n_cards)
+        }
+      }
+    }
+    _ => {
+      println!("Encountered unknown error when parsing input");
+      prompt_for_pick(n_cards)
+    }
+  }
+}
+
+// Practice a draft as Blue against the drafting agent, then play the drafted hands
+fn run_draft(args:&[String]) {
+  let cards_per_tier = args.first().and_then(|x| x.parse::<usize>().ok()).unwrap_or(4).max(2);
+  let seed = args.get(1).and_then(|x| x.parse::<usize>().ok()).unwrap_or(0);
+  let mut rng = StdRng::from_seed(&[seed][..]);
+  let rules = match prompt_for_rules().with(Rule::Draft).validate() {
+    Ok(rules) => rules,
+    Err(e) => { println!("{}", e); return }
+  };
+  let first_picker = prompt_for_first_player();
+  let mut draft = draft::Draft::new(&api::read_cardlist(), cards_per_tier, first_picker, &mut rng);
+
+  while !draft.is_finished() {
+    let idx = match draft.turn {
+      Player::Blue => {
+        let values = match draft::evaluate_picks(&draft, rules, &mut rng, DRAFT_ROLLOUTS, DRAFT_PLAYOUTS) {
+          Ok(values) => values,
+          Err(e) => { println!("{}", e); return }
+        };
+        for (idx, (card, value)) in draft.available().iter().zip(&values).enumerate() {
+          let stats = &card.stats;
+          println!("{}: {} [{} {} {} {}] value {:.2}", idx, card.name, format_number(stats.top), format_number(stats.right), format_number(stats.bottom), format_number(stats.left), value);
+        }
+        prompt_for_pick(values.len())
+      }
+      Player::Red => match draft::choose_pick(&draft, rules, &mut rng, DRAFT_ROLLOUTS, DRAFT_PLAYOUTS) {
+        Ok(Some(idx)) => idx,
+        Ok(None) => { println!("No cards left to pick"); return }
+        Err(e) => { println!("{}", e); return }
+      }
+    };
+    println!("{} picks {}", draft.turn, draft.available()[idx].name);
+    draft.pick(idx);
+  }
+
+  let first_player = prompt_for_first_player();
+  let mut game = draft.into_game(first_player, Player::Blue, rules);
+  if game.rules.contains(Rule::Elemental) {
+    game.assign_random_elements(&mut rng);
+  }
+  let objective = prompt_for_objective();
+  play_game(&mut game, &SearchSettings { objective, pool: Vec::new(), think_time: DEFAULT_THINK_TIME, tablebase: load_tablebase(), book: load_book() });
+}
+
+// The default when no time is given; None unless it's a positive number of seconds
+fn parse_think_time(arg:Option<&str>) -> Option<Duration> {
+  match arg {
+    None => Some(DEFAULT_THINK_TIME),
+    Some(x) => x.parse::<f32>().ok().filter(|secs| secs.is_finite() && *secs > 0.0).and_then(|secs| Duration::try_from_secs_f32(secs).ok())
+  }
+}
+
+fn main() -> (){
+
+  let args = std::env::args().collect::<Vec<String>>();
+  match args.get(1).map(|x| x.as_str()) {
+    Some("winrate") => run_win_rate(&args[2..]),
+    Some("draft") => run_draft(&args[2..]),
+    Some("book") => run_book(&args[2..]),
+    // Otherwise the argument, if any, is how many seconds the AI thinks for each move
+    think_seconds => {
+      let Some(think_time) = parse_think_time(think_seconds) else {
+        println!("Usage: triple_triad [think_seconds] | winrate ... | draft ... | book ...");
+        return
+      };
+      let mut game = initialize_game();
+      let objective = prompt_for_objective();
+      let pool = if game.has_hidden_cards() { prompt_for_candidate_pool() } else { Vec::new() };
+      play_game(&mut game, &SearchSettings { objective, pool, think_time, tablebase: load_tablebase(), book: load_book() });
+    }
+  }
 
   //let game = game.clone();
   
@@ -227,4 +1012,3 @@ fn main() -> (){
   //explore_cardlist()
 
 }
-
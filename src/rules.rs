@@ -0,0 +1,185 @@
+use std::{fmt, hash::{Hash, Hasher}, sync::Arc};
+use itertools::Itertools;
+
+use crate::{game::*, types::*};
+
+// The value board edges take under Same Wall and Plus Wall
+const WALL_VALUE: isize = 10;
+
+// Sudden Death stops replaying draws after this many rounds
+const SUDDEN_DEATH_ROUND_LIMIT: usize = 5;
+
+// What happens once the board is full
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoundEnd
+{ Finished(GameResult)
+// Play another round, as Sudden Death does after a draw
+, Replay
+}
+
+// The hooks a rule can use to change how the game plays. Every hook defaults to the standard game,
+// so a rule only implements the ones it needs. The built-in `Rule`s implement this below; house rules
+// implement it on their own types and are added to `Game.house_rules`.
+pub trait GameRule: Send + Sync {
+  fn name(&self) -> String;
+
+  // Before-move validation: whether the card at `hand_idx` in the current player's hand may be played this turn
+  fn allows_card(&self, _game:&Game, _hand_idx:usize) -> bool { true }
+
+  // Before-move validation: whether a move with a playable card is allowed
+  fn allows_move(&self, _game:&Game, _user_move:&Move) -> bool { true }
+
+  // Value modification: a flat modifier for every side of `card`, which is at `coords` if it's on the board.
+  // Modifiers from every rule are summed before the result is clamped to 1..=10.
+  fn stat_modifier(&self, _game:&Game, _card:&GameCard, _coords:Option<&Coord>) -> isize { 0 }
+
+  // Capture detection: adjusts the margin by which the placed card's value beats the board card's; positive captures.
+  // Rules are applied in turn, each receiving the margin the previous rules produced.
+  fn capture_margin(&self, _game:&Game, _placed_value:isize, _board_value:isize, margin:isize) -> isize { margin }
+
+  // Post-capture combos: the neighbours this rule flips, which then go on to capture as a combo
+  fn combo_captures(&self, _game:&Game, _user_move:&Move, _neighbors:&[Comparison], _walls:&[Side]) -> Vec<Coord> { Vec::new() }
+
+  // End-of-game: overrides the result once the board is full; the first rule returning Some decides
+  fn end_of_round(&self, _game:&Game, _result:GameResult) -> Option<RoundEnd> { None }
+}
+
+// Every built-in rule, so active ones can be handed out as `&'static dyn GameRule`
+static BUILT_IN_RULES: [Rule; 18] = Rule::ALL;
+
+pub fn built_in_rules<'a>(rules:RuleSet) -> impl Iterator<Item=&'a (dyn GameRule + 'static)> {
+  BUILT_IN_RULES.iter().filter(move |rule| rules.contains(**rule)).map(|rule| rule as &dyn GameRule)
+}
+
+impl GameRule for Rule {
+  fn name(&self) -> String {
+    format!("{:?}", self)
+  }
+
+  fn allows_card(&self, game:&Game, hand_idx:usize) -> bool {
+    match self {
+      Rule::Order => game.hands[&game.turn].first_card_idx() == Some(hand_idx),
+      Rule::Chaos => game.forced_card.is_none_or(|forced| forced == hand_idx),
+      _ => true
+    }
+  }
+
+  fn stat_modifier(&self, game:&Game, card:&GameCard, coords:Option<&Coord>) -> isize {
+    match (self, card.card.stats.tribe) {
+      (Rule::Ascension, Some(tribe)) => game.board.tribe_counts[tribe.idx()] as isize,
+      (Rule::Descension, Some(tribe)) => -(game.board.tribe_counts[tribe.idx()] as isize),
+      // +1 on a square of the card's own element, -1 on any other elemental square
+      (Rule::Elemental, _) => match coords.and_then(|coords| game.board.square(coords).1) {
+        Some(element) if card.card.element == Some(element) => 1,
+        Some(_) => -1,
+        None => 0
+      },
+      _ => 0
+    }
+  }
+
+  // Under Reverse, lower values win. Under Fallen Ace, a 1 beats an A (or an A beats a 1, when combined with Reverse).
+  fn capture_margin(&self, game:&Game, placed_value:isize, board_value:isize, margin:isize) -> isize {
+    let reverse = game.rules.contains(Rule::Reverse);
+    match (self, placed_value, board_value) {
+      (Rule::Reverse, _, _) => -margin,
+      (Rule::FallenAce, 1, 10) if !reverse => 1,
+      (Rule::FallenAce, 10, 1) if reverse => 1,
+      _ => margin
+    }
+  }
+
+  // Walls (board edges, under Same Wall or Plus Wall) count towards Same and Plus but are never flipped
+  fn combo_captures(&self, game:&Game, user_move:&Move, neighbors:&[Comparison], walls:&[Side]) -> Vec<Coord> {
+    match self {
+      // Same is achieved when two or more neighbors have touching values equal to the placed card's values
+      Rule::Same => {
+        let matching_coords =
+          neighbors
+          .iter()
+          .filter(|(coords, card, side)| { let (new_value, board_value) = game.touching_values(coords, card, user_move, side); new_value == board_value })
+          .map(|(coords, _, _)| *coords)
+          .collect::<Vec<Coord>>();
+        let matching_walls =
+          if game.rules.contains(Rule::SameWall) { walls.iter().filter(|side| game.placed_value(user_move, side) == WALL_VALUE).count() }
+          else { 0 };
+        if matching_coords.len() + matching_walls >= 2 { matching_coords } else { Vec::new() }
+      }
+      // Plus is achieved when two or more neighbors have touching values summing to the same total
+      Rule::Plus => {
+        let wall_sums =
+          if game.rules.contains(Rule::PlusWall) { walls.iter().map(|side| game.placed_value(user_move, side) + WALL_VALUE).collect() }
+          else { Vec::new() };
+        neighbors
+        .iter()
+        .into_group_map_by(|(coords, card, side)| { let (new_value, board_value) = game.touching_values(coords, card, user_move, side); new_value + board_value })
+        .into_iter()
+        .filter(|(sum, group)| group.len() + wall_sums.iter().filter(|wall_sum| *wall_sum == sum).count() >= 2)
+        .flat_map(|(_, group)| group)
+        .map(|(coords, _, _)| *coords)
+        .collect::<Vec<Coord>>()
+      }
+      _ => Vec::new()
+    }
+  }
+
+  fn end_of_round(&self, game:&Game, result:GameResult) -> Option<RoundEnd> {
+    match (self, result) {
+      (Rule::SuddenDeath, GameResult::Draw) if game.round < SUDDEN_DEATH_ROUND_LIMIT => Some(RoundEnd::Replay),
+      _ => None
+    }
+  }
+}
+
+// House rule: cards in a corner get +1 on every side
+pub struct CornerBonus;
+impl GameRule for CornerBonus {
+  fn name(&self) -> String {
+    "CornerBonus".to_string()
+  }
+
+  fn stat_modifier(&self, game:&Game, _card:&GameCard, coords:Option<&Coord>) -> isize {
+    match coords {
+      Some(coords) if [0, game.board.rows() - 1].contains(&coords.row) && [0, game.board.cols() - 1].contains(&coords.col) => 1,
+      _ => 0
+    }
+  }
+}
+
+// Looks up a house rule by name, the way `Rule` parses built-in ones
+pub fn house_rule(s:&str) -> Result<Arc<dyn GameRule>, String> {
+  let normalized = s.trim().to_lowercase().replace([' ', '_', '-'], "");
+  match normalized.as_str() {
+    "cornerbonus" => Ok(Arc::new(CornerBonus)),
+    _ => Err(format!("Unknown house rule \"{}\"", s.trim()))
+  }
+}
+
+// House rules in play alongside the built-in `RuleSet`; compared and hashed by name, so `Game` keeps its derives
+#[derive(Clone, Default)]
+pub struct HouseRules(pub Vec<Arc<dyn GameRule>>);
+impl HouseRules {
+  pub fn iter(&self) -> impl Iterator<Item=&(dyn GameRule + 'static)> + '_ {
+    self.0.iter().map(|rule| rule.as_ref())
+  }
+
+  pub fn names(&self) -> Vec<String> {
+    self.0.iter().map(|rule| rule.name()).collect()
+  }
+}
+impl fmt::Debug for HouseRules {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "HouseRules({:?})", self.names())
+  }
+}
+impl PartialEq for HouseRules {
+  fn eq(&self, other: &Self) -> bool {
+    self.names() == other.names()
+  }
+}
+impl Eq for HouseRules {}
+impl Hash for HouseRules {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.names().hash(state)
+  }
+}
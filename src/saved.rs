@@ -0,0 +1,47 @@
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+// What's written to disk: the data, tagged with the version of its format
+#[derive(Serialize, Deserialize)]
+struct SavedFile<T>
+{ version: u32
+, entries: T
+}
+
+// Reads data saved by `save`. A missing file just means nothing has been saved yet, and a file from another
+// version is ignored rather than misread, so both give None. `description` names the data in errors.
+pub fn load<T: DeserializeOwned>(filename:&str, version:u32, description:&str) -> Result<Option<T>, String> {
+  let contents = match std::fs::read_to_string(filename) {
+    Ok(contents) => contents,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(format!("Failed to read {} {}: {}", description, filename, e))
+  };
+  let parse_error = |e:serde_json::Error| format!("Failed to parse {} {}: {}", description, filename, e);
+  let file : SavedFile<serde_json::Value> = serde_json::from_str(&contents).map_err(parse_error)?;
+  match file.version {
+    saved_version if saved_version == version => serde_json::from_value(file.entries).map(Some).map_err(parse_error),
+    _ => Ok(None)
+  }
+}
+
+pub fn save<T: Serialize>(filename:&str, version:u32, entries:&T, description:&str) -> Result<(), String> {
+  let contents = serde_json::to_string(&SavedFile { version, entries }).map_err(|e| format!("Failed to serialize {}: {}", description, e))?;
+  std::fs::write(filename, contents).map_err(|e| format!("Failed to write {} {}: {}", description, filename, e))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  #[test]
+  fn round_trips_and_ignores_other_versions() {
+    let filename = std::env::temp_dir().join(format!("triple_triad_saved_{}.json", std::process::id()));
+    let filename = filename.to_str().unwrap();
+    let entries = HashMap::from([(1u64, 2isize), (3, -4)]);
+    super::save(filename, 2, &entries, "test data").unwrap();
+    assert_eq!(super::load::<HashMap<u64, isize>>(filename, 2, "test data").unwrap(), Some(entries));
+    // Another version's entries needn't even parse as this version's type
+    assert_eq!(super::load::<Vec<String>>(filename, 3, "test data").unwrap(), None);
+    std::fs::remove_file(filename).unwrap();
+    assert_eq!(super::load::<HashMap<u64, isize>>(filename, 2, "test data").unwrap(), None);
+  }
+}
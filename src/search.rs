@@ -0,0 +1,87 @@
+use std::{fmt, time::{Duration, Instant}};
+use mcts::{MCTSManager, transposition_table::ApproxTable};
+
+use crate::game::*;
+
+// The search runs in chunks sized to take about this long, reporting progress in between
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Before there's a playout rate to size chunks by
+const FIRST_CHUNK_PLAYOUTS: u32 = 1_000;
+
+#[derive(Clone, Debug)]
+pub struct SearchProgress
+{ pub best_move: Option<Action>
+// The best move's share of the root's visits
+, pub visit_share: f32
+, pub playouts: u64
+, pub nodes: usize
+, pub elapsed: Duration
+}
+impl SearchProgress {
+  pub fn nodes_per_second(&self) -> f64 {
+    self.nodes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+  }
+
+  pub fn playouts_per_second(&self) -> f64 {
+    self.playouts as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+  }
+}
+impl fmt::Display for SearchProgress {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let best_move = match &self.best_move {
+      Some(Action::Play(user_move)) => {
+        let stats = &user_move.card.card.stats;
+        let sides = [stats.top, stats.right, stats.bottom, stats.left].map(format_number).join(" ");
+        format!("[{}] at {},{}", sides, user_move.coords.row + 1, user_move.coords.col + 1)
+      }
+      Some(Action::Force(idx)) => format!("Chaos picks card {}", idx),
+      None => "none".to_string()
+    };
+    write!(f, "{:.1}s: best {} ({:.1}% of {} playouts), {:.0} nodes/s",
+      self.elapsed.as_secs_f32(), best_move, 100.0 * self.visit_share, self.playouts, self.nodes_per_second())
+  }
+}
+
+// The current progress, and how many visits the best move leads the runner-up by
+fn inspect(mcts:&MCTSManager<MyMCTS>, elapsed:Duration) -> (SearchProgress, u64) {
+  let mut visits = mcts.tree().root_node().moves().map(|info| info.visits()).collect::<Vec<u64>>();
+  visits.sort_unstable_by(|a, b| b.cmp(a));
+  let playouts = visits.iter().sum::<u64>();
+  let lead = match visits[..] {
+    [best, second, ..] => best - second,
+    // A forced move can't be overtaken
+    _ => u64::MAX
+  };
+  let progress = SearchProgress
+    { best_move: mcts.best_move()
+    , visit_share: visits.first().map_or(0.0, |best| *best as f32 / playouts.max(1) as f32)
+    , playouts
+    , nodes: mcts.tree().num_nodes()
+    , elapsed
+    };
+  (progress, lead)
+}
+
+// Searches for up to `budget` of wall-clock time, calling `on_progress` after every chunk of playouts.
+// The best move is the most visited one, so the search stops early once its lead over the runner-up is
+// more than the playouts the rest of the budget could add.
+pub fn timed_search<F: FnMut(&SearchProgress)>(game:&Game, evaluator:MyEvaluator, budget:Duration, threads:usize, mut on_progress:F) -> MCTSManager<MyMCTS> {
+  let mut mcts = MCTSManager::new(game.clone(), MyMCTS, evaluator, ChanceUCTPolicy::new(0.5), ApproxTable::new(1024));
+  let start = Instant::now();
+  let mut chunk = FIRST_CHUNK_PLAYOUTS;
+  loop {
+    mcts.playout_n_parallel(chunk, threads);
+    let elapsed = start.elapsed();
+    let (progress, lead) = inspect(&mcts, elapsed);
+    on_progress(&progress);
+
+    let remaining = budget.saturating_sub(elapsed);
+    let playouts_left = progress.playouts_per_second() * remaining.as_secs_f64();
+    if remaining.is_zero() || progress.best_move.is_none() || lead as f64 > playouts_left {
+      return mcts
+    }
+    let chunk_time = CHECK_INTERVAL.min(remaining);
+    chunk = ((progress.playouts_per_second() * chunk_time.as_secs_f64()) as u32).max(threads as u32);
+  }
+}
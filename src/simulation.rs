@@ -0,0 +1,75 @@
+use std::fmt;
+use std::collections::HashMap;
+use mcts::{MCTSManager, transposition_table::ApproxTable};
+use rand::{Rng, SeedableRng, StdRng};
+
+use crate::{collection::{Collection, DealLog, deal_random_hands}, game::*};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WinRate
+{ pub wins: usize
+, pub draws: usize
+, pub losses: usize
+}
+impl WinRate {
+  pub fn games(&self) -> usize {
+    self.wins + self.draws + self.losses
+  }
+
+  pub fn record(&mut self, result:GameResult, player:Player) {
+    match result {
+      GameResult::Win(winner) if winner == player => self.wins += 1,
+      GameResult::Win(_) => self.losses += 1,
+      GameResult::Draw => self.draws += 1,
+    }
+  }
+}
+impl fmt::Display for WinRate {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let percent = |count:usize| 100.0 * count as f32 / self.games().max(1) as f32;
+    write!(f, "{} games: {} wins ({:.1}%), {} draws ({:.1}%), {} losses ({:.1}%)",
+      self.games(), self.wins, percent(self.wins), self.draws, percent(self.draws), self.losses, percent(self.losses))
+  }
+}
+
+// Plays the game to the end with the AI choosing for both sides, rolling Chaos with the given RNG
+pub fn self_play<R: Rng>(game:&mut Game, rng:&mut R, playouts:u64) -> Result<GameResult, String> {
+  loop {
+    if game.awaiting_chaos() {
+      game.roll_forced_card(rng);
+    }
+    let mut mcts = MCTSManager::new(game.clone(), MyMCTS, MyEvaluator::default(), ChanceUCTPolicy::new(0.5), ApproxTable::new(1024));
+    mcts.playout_n(playouts);
+    let next_move = match mcts.best_move() {
+      Some(Action::Play(next_move)) => next_move,
+      _ => return Err(format!("Search found no move for {} in\n{}", game.turn, game))
+    };
+    if let Some(MoveResult::Finished(result)) = game.make_move(&next_move) {
+      return Ok(result)
+    }
+  }
+}
+
+// Deals `games` random hands from the two collections and plays each one out, from `my_color`'s point of view.
+// Roulette is rolled afresh from `roulette_pool` for every game.
+pub fn estimate_win_rate(collections:&HashMap<Player, Collection>, my_color:Player, rules:RuleSet, roulette_pool:&[Rule], games:usize, seed:usize, playouts:u64) -> Result<(WinRate, Vec<DealLog>), String> {
+  let mut rng = StdRng::from_seed(&[seed][..]);
+  let mut win_rate = WinRate::default();
+  let mut deals = Vec::new();
+  for _ in 0..games {
+    let first_player = if rng.gen() { Player::Blue } else { Player::Red };
+    let rules = resolve_roulette(rules, &mut rng, roulette_pool, ROULETTE_PICKS);
+    let mut game = Game::new(first_player, my_color, rules);
+    let deal = deal_random_hands(&mut game, collections, rng.gen())?;
+    if game.rules.contains(Rule::Swap) {
+      game.random_swap(&mut rng);
+    }
+    if game.rules.contains(Rule::Elemental) {
+      game.assign_random_elements(&mut rng);
+    }
+    let result = self_play(&mut game, &mut rng, playouts)?;
+    win_rate.record(result, my_color);
+    deals.push(deal);
+  }
+  Ok((win_rate, deals))
+}
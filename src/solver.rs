@@ -0,0 +1,259 @@
+use std::{fmt, collections::HashMap, sync::Arc};
+use itertools::Itertools;
+use mcts::GameState;
+
+use crate::{game::*, tablebase::Tablebase};
+
+// Positions with at most this many open squares are solved exactly rather than searched with MCTS
+pub const MAX_OPEN_SQUARES: usize = 7;
+
+// Wider than any margin a board can produce
+const INFINITY: isize = 1000;
+
+// How a stored value relates to the true value, after alpha-beta cut the search short
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Bound
+{ Exact
+, Lower
+, Upper
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict
+{ Win
+, Draw
+, Loss
+}
+
+// The game-theoretic value of a move: the final card difference, for the player making it, with perfect play
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MoveOutcome
+{ pub user_move: Move
+, pub margin: isize
+}
+impl MoveOutcome {
+  pub fn verdict(&self) -> Verdict {
+    match self.margin {
+      margin if margin > 0 => Verdict::Win,
+      margin if margin < 0 => Verdict::Loss,
+      _ => Verdict::Draw
+    }
+  }
+}
+impl fmt::Display for MoveOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.verdict() {
+      Verdict::Draw => write!(f, "Draw"),
+      verdict => write!(f, "{:?} by {}", verdict, self.margin.abs())
+    }
+  }
+}
+
+// Exact search only works without chance or hidden cards; Chaos and closed hands need MCTS
+pub fn can_solve(game:&Game) -> bool {
+  !game.has_hidden_cards()
+    && !game.rules.contains(Rule::Chaos)
+    && game.open_squares().len() <= MAX_OPEN_SQUARES
+}
+
+// Cards `player` controls less cards the other player controls
+pub fn margin(game:&Game, player:Player) -> isize {
+  game.get_score(&player) - game.get_score(&player.other())
+}
+
+// Each legal move with the position it leads to, and the final margin if it ends the round.
+// Moves that gain the most cards straight away come first, so alpha-beta cuts off sooner.
+pub fn children(game:&Game) -> Vec<(Move, Game, Option<isize>)> {
+  let player = game.turn;
+  game.available_moves()
+    .into_iter()
+    .filter_map(|action| match action {
+      Action::Play(user_move) => Some(user_move),
+      Action::Force(_) => None
+    })
+    .map(|user_move| {
+      let mut child = game.clone();
+      let terminal = match child.make_move(&user_move) {
+        Some(MoveResult::Finished(_)) => Some(margin(&child, player)),
+        Some(MoveResult::NextRound) => Some(0),
+        _ => None
+      };
+      (user_move, child, terminal)
+    })
+    .sorted_by_key(|(_, child, _)| std::cmp::Reverse(margin(child, player)))
+    .collect()
+}
+
+// Negamax with alpha-beta pruning over the rest of the round. A Sudden Death replay counts as a draw,
+// since solving the next round would mean searching it from an empty board.
+#[derive(Default)]
+pub struct Solver
+// Keyed by the whole canonical position rather than its hash, so a collision can't pass off one position's
+// value as another's
+{ table: HashMap<Vec<u8>, (isize, Bound)>
+, pub nodes: usize
+// Late positions are looked up here instead of searched
+, tablebase: Option<Arc<Tablebase>>
+}
+impl Solver {
+  pub fn new() -> Solver {
+    Solver::default()
+  }
+
+  pub fn with_tablebase(tablebase:Arc<Tablebase>) -> Solver {
+    Solver { tablebase: Some(tablebase), ..Solver::default() }
+  }
+
+  // Every legal move with its exact outcome, best first
+  pub fn solve_moves(&mut self, game:&Game) -> Vec<MoveOutcome> {
+    let mut outcomes =
+      children(game)
+      .into_iter()
+      .map(|(user_move, child, terminal)| {
+        let margin = terminal.unwrap_or_else(|| -self.negamax(&child, -INFINITY, INFINITY));
+        MoveOutcome { user_move, margin }
+      })
+      .collect::<Vec<MoveOutcome>>();
+    outcomes.sort_by_key(|outcome| std::cmp::Reverse(outcome.margin));
+    outcomes
+  }
+
+  // The final margin for the player to move, with perfect play from both sides
+  pub fn solve(&mut self, game:&Game) -> isize {
+    self.negamax(game, -INFINITY, INFINITY)
+  }
+
+  fn negamax(&mut self, game:&Game, mut alpha:isize, beta:isize) -> isize {
+    self.nodes += 1;
+    let (key, _) = game.canonical_form();
+    match self.table.get(&key) {
+      Some((value, Bound::Exact)) => return *value,
+      Some((value, Bound::Lower)) if *value >= beta => return *value,
+      Some((value, Bound::Upper)) if *value <= alpha => return *value,
+      _ => ()
+    }
+
+    if let Some(value) = self.tablebase.as_ref().and_then(|tablebase| tablebase.value(game)) {
+      return value
+    }
+
+    let children = children(game);
+    if children.is_empty() {
+      return margin(game, game.turn)
+    }
+
+    let original_alpha = alpha;
+    let mut best = -INFINITY;
+    for (_, child, terminal) in children {
+      let value = match terminal {
+        Some(value) => value,
+        None => -self.negamax(&child, -beta, -alpha)
+      };
+      best = best.max(value);
+      alpha = alpha.max(value);
+      if alpha >= beta {
+        break
+      }
+    }
+
+    let bound = match best {
+      best if best <= original_alpha => Bound::Upper,
+      best if best >= beta => Bound::Lower,
+      _ => Bound::Exact
+    };
+    self.table.insert(key, (best, bound));
+    best
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{Rng, SeedableRng, StdRng};
+  use crate::types::*;
+
+  fn card(top:usize, right:usize, bottom:usize, left:usize) -> GameCard {
+    let stats = CardStats { top, right, bottom, left, tribe: None };
+    GameCard { card: Card { name: String::new(), id: 0, stars: 1, stats, element: None }, player: None, owner: None }
+  }
+
+  // A 3x3 board with `open` squares left, Blue having gone first, and the cards dealt by `next_card`
+  fn endgame<F: FnMut() -> GameCard>(rules:&[Rule], open:usize, mut next_card:F) -> Game {
+    let mut game = Game::new(Player::Blue, Player::Blue, rules.iter().copied().collect());
+    let placed = 9 - open;
+    for (idx, coords) in game.board.coords().take(placed).collect::<Vec<Coord>>().into_iter().enumerate() {
+      let player = if idx % 2 == 0 { Player::Blue } else { Player::Red };
+      let mut card = next_card();
+      card.player = Some(player);
+      card.owner = Some(player);
+      *game.board.square_mut(&coords) = Square(Some(card), None);
+    }
+    for _ in 0..5 - placed.div_ceil(2) { game.add_card_to_hand(next_card(), Player::Blue); }
+    for _ in 0..5 - placed / 2 { game.add_card_to_hand(next_card(), Player::Red); }
+    game.turn = if placed.is_multiple_of(2) { Player::Blue } else { Player::Red };
+    game
+  }
+
+  fn random_endgame(rules:&[Rule], open:usize, seed:usize) -> Game {
+    let mut rng = StdRng::from_seed(&[seed][..]);
+    endgame(rules, open, || card(rng.gen_range(1, 11), rng.gen_range(1, 11), rng.gen_range(1, 11), rng.gen_range(1, 11)))
+  }
+
+  // Every line played out, with no pruning or table
+  fn minimax(game:&Game) -> isize {
+    game.available_moves().into_iter().filter_map(|action| match action {
+      Action::Play(user_move) => Some(move_value(game, &user_move)),
+      Action::Force(_) => None
+    })
+    .max()
+    .unwrap_or_else(|| margin(game, game.turn))
+  }
+
+  fn move_value(game:&Game, user_move:&Move) -> isize {
+    let mut child = game.clone();
+    match child.make_move(user_move) {
+      Some(MoveResult::Finished(_)) => margin(&child, game.turn),
+      Some(MoveResult::NextRound) => 0,
+      _ => -minimax(&child)
+    }
+  }
+
+  #[test]
+  fn solves_like_plain_minimax() {
+    let rule_sets:[&[Rule];5] = [&[], &[Rule::Same], &[Rule::Plus], &[Rule::Same, Rule::Plus], &[Rule::SuddenDeath]];
+    for rules in rule_sets {
+      for open in [2, 3] {
+        for seed in 0..20 {
+          let game = random_endgame(rules, open, seed);
+          let outcomes = Solver::new().solve_moves(&game);
+          assert_eq!(outcomes.len(), game.available_moves().len());
+          for outcome in &outcomes {
+            assert_eq!(outcome.margin, move_value(&game, &outcome.user_move), "{:?} {:?}", rules, outcome.user_move);
+          }
+          assert_eq!(Solver::new().solve(&game), minimax(&game));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn same_and_plus_change_the_outcome() {
+    let changed = (0..20).filter(|seed| {
+      let plain = Solver::new().solve(&random_endgame(&[], 3, *seed));
+      plain != Solver::new().solve(&random_endgame(&[Rule::Same, Rule::Plus], 3, *seed))
+    });
+    assert!(changed.count() > 0);
+  }
+
+  #[test]
+  fn sudden_death_replays_count_as_draws() {
+    // Equal cards never capture, so the board ends up five to four: a draw, replayed under Sudden Death
+    let game = endgame(&[Rule::SuddenDeath], 2, || card(5, 5, 5, 5));
+    let outcomes = Solver::new().solve_moves(&game);
+    assert!(outcomes.iter().all(|outcome| outcome.verdict() == Verdict::Draw));
+    let (_, child, _) = children(&game).remove(0);
+    let last_move = child.available_moves().remove(0);
+    let Action::Play(last_move) = last_move else { panic!("{:?}", last_move) };
+    assert!(matches!(child.clone().make_move(&last_move), Some(MoveResult::NextRound)));
+  }
+}
@@ -0,0 +1,97 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use crate::{game::*, saved, solver::Solver};
+
+pub const TABLEBASE_FILENAME: &str = "tablebase.json";
+
+// Positions with at most this many open squares are solved exhaustively and cached
+pub const TABLEBASE_MAX_OPEN_SQUARES: usize = 4;
+
+// Bumped whenever the key or value format changes
+const TABLEBASE_VERSION: u32 = 2;
+
+// Exact results of late-game positions: the final margin for the player to move, with perfect play,
+// keyed by `Game::canonical_hash`. Filled lazily as positions come up, and shared between search threads.
+// The key is only 64 bits, so two positions could in principle collide, but with a million positions stored
+// the odds that any two do are about one in forty million.
+#[derive(Debug, Default)]
+pub struct Tablebase
+{ entries: RwLock<HashMap<u64, isize>>
+}
+impl Tablebase {
+  pub fn new() -> Tablebase {
+    Tablebase::default()
+  }
+
+  pub fn load(filename:&str) -> Result<Tablebase, String> {
+    let entries = saved::load(filename, TABLEBASE_VERSION, "tablebase")?.unwrap_or_default();
+    Ok(Tablebase { entries: RwLock::new(entries) })
+  }
+
+  pub fn save(&self, filename:&str) -> Result<(), String> {
+    saved::save(filename, TABLEBASE_VERSION, &*self.entries.read().unwrap(), "tablebase")
+  }
+
+  pub fn positions(&self) -> usize {
+    self.entries.read().unwrap().len()
+  }
+
+  // Only fully known positions without chance can be solved
+  pub fn covers(game:&Game) -> bool {
+    !game.has_hidden_cards()
+      && !game.rules.contains(Rule::Chaos)
+      && game.open_squares().len() <= TABLEBASE_MAX_OPEN_SQUARES
+  }
+
+  // The exact final margin for the player to move, solving and caching the position if it's new
+  pub fn value(&self, game:&Game) -> Option<isize> {
+    Tablebase::covers(game).then(|| self.solve(game))
+  }
+
+  // Solved with alpha-beta, which takes a few milliseconds at most, so it's fine on a search thread. Only the
+  // root's value is exact, so only it is cached.
+  fn solve(&self, game:&Game) -> isize {
+    let key = game.canonical_hash();
+    if let Some(value) = self.entries.read().unwrap().get(&key) {
+      return *value
+    }
+    let value = Solver::new().solve(game);
+    self.entries.write().unwrap().insert(key, value);
+    value
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::types::*;
+
+  fn card(top:usize, right:usize, bottom:usize, left:usize) -> GameCard {
+    let stats = CardStats { top, right, bottom, left, tribe: None };
+    GameCard { card: Card { name: String::new(), id: 0, stars: 1, stats, element: None }, player: None, owner: None }
+  }
+
+  #[test]
+  fn values_match_the_solver() {
+    let mut game = Game::new(Player::Blue, Player::Blue, [Rule::Same, Rule::Plus].into_iter().collect());
+    let cards = [card(3, 7, 2, 5), card(6, 1, 8, 4), card(2, 9, 3, 3), card(7, 4, 1, 6), card(5, 5, 6, 2)];
+    for (idx, coords) in game.board.coords().take(5).collect::<Vec<Coord>>().into_iter().enumerate() {
+      let player = if idx % 2 == 0 { Player::Blue } else { Player::Red };
+      *game.board.square_mut(&coords) = Square(Some(GameCard { player: Some(player), owner: Some(player), ..cards[idx].clone() }), None);
+    }
+    game.add_card_to_hand(card(8, 2, 4, 7), Player::Blue).add_card_to_hand(card(1, 6, 9, 5), Player::Blue);
+    game.add_card_to_hand(card(4, 8, 5, 1), Player::Red).add_card_to_hand(card(9, 3, 2, 8), Player::Red).add_card_to_hand(card(6, 6, 3, 4), Player::Red);
+    game.turn = Player::Red;
+
+    let tablebase = Tablebase::new();
+    assert_eq!(tablebase.value(&game), Some(Solver::new().solve(&game)));
+    assert_eq!(tablebase.positions(), 1);
+    // A second lookup is served from the table
+    assert_eq!(tablebase.value(&game), Some(Solver::new().solve(&game)));
+    assert_eq!(tablebase.positions(), 1);
+
+    // Five open squares is past what the tablebase covers
+    game.board.squares[1][1] = Square::default();
+    assert_eq!(tablebase.value(&game), None);
+  }
+}
@@ -0,0 +1,202 @@
+use std::{fmt, str::FromStr};
+use itertools::Itertools;
+
+use crate::{game::{Game, GameResult, Player}, types::Card};
+
+// FF8-style rules deciding which cards change hands once a game is over
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TradeRule
+// The winner takes one of the loser's cards
+{ One
+// The winner takes as many of the loser's cards as the difference in score
+, Diff
+// Every card goes to whoever controls it at the end, win or lose
+, Direct
+// The winner takes every card the loser brought
+, All
+} impl FromStr for TradeRule {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "one" => Ok(TradeRule::One),
+      "diff" | "difference" => Ok(TradeRule::Diff),
+      "direct" => Ok(TradeRule::Direct),
+      "all" => Ok(TradeRule::All),
+      _ => Err(format!("Unknown trade rule \"{}\"", s.trim()))
+    }
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Transfer
+{ pub card: Card
+, pub from: Player
+, pub to: Player
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Trade(pub Vec<Transfer>);
+impl Trade {
+  pub fn taking(cards:Vec<Card>, from:Player, to:Player) -> Trade {
+    Trade(cards.into_iter().map(|card| Transfer { card, from, to }).collect())
+  }
+
+  pub fn gained(&self, player:Player) -> Vec<&Card> {
+    self.0.iter().filter(|transfer| transfer.to == player).map(|transfer| &transfer.card).collect()
+  }
+
+  pub fn lost(&self, player:Player) -> Vec<&Card> {
+    self.0.iter().filter(|transfer| transfer.from == player).map(|transfer| &transfer.card).collect()
+  }
+
+  // Stars gained less stars lost; a rough measure of what the trade is worth to `player`. Cards that couldn't be
+  // identified have no stars, so they're left out.
+  pub fn value_for(&self, player:Player) -> isize {
+    let stars = |cards:Vec<&Card>| cards.iter().map(|card| card.stars as isize).sum::<isize>();
+    stars(self.gained(player)) - stars(self.lost(player))
+  }
+}
+impl fmt::Display for Trade {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.0.is_empty() {
+      return write!(f, "No cards change hands")
+    }
+    let transfers =
+      self.0
+      .iter()
+      .map(|transfer| format!("{}'s {} ({}*) goes to {}", transfer.from, transfer.card.name, transfer.card.stars, transfer.to))
+      .collect::<Vec<String>>();
+    write!(f, "{}", transfers.join("\n"))
+  }
+}
+
+// The cards `player` brought to the game and could lose, most valuable first
+pub fn stakes(game:&Game, player:Player) -> Vec<Card> {
+  let side_sum = |card:&Card| card.stats.top + card.stats.right + card.stats.bottom + card.stats.left;
+  game.cards_owned_by(player)
+    .into_iter()
+    .map(|card| card.card)
+    .sorted_by_key(|card| std::cmp::Reverse((card.stars, side_sum(card))))
+    .collect()
+}
+
+// How many of the loser's cards the winner picks under One, Diff and All; Direct involves no picks
+pub fn cards_to_pick(game:&Game, rule:TradeRule, result:&GameResult) -> usize {
+  let winner = match result {
+    GameResult::Win(winner) => *winner,
+    GameResult::Draw => return 0
+  };
+  let available = stakes(game, winner.other()).len();
+  match rule {
+    TradeRule::One => 1.min(available),
+    TradeRule::Diff => ((game.get_score(&winner) - game.get_score(&winner.other())) as usize).min(available),
+    TradeRule::All => available,
+    TradeRule::Direct => 0
+  }
+}
+
+// The trade once the game ends, assuming a winner who picks takes the loser's most valuable cards
+pub fn compute_trade(game:&Game, rule:TradeRule, result:&GameResult) -> Trade {
+  match (rule, result) {
+    (TradeRule::Direct, _) => Trade(
+      game.board.squares
+      .iter()
+      .flatten()
+      .filter_map(|square| square.0.as_ref())
+      .filter_map(|card| match (card.owner, card.player) {
+        (Some(owner), Some(player)) if owner != player => Some(Transfer { card: card.card.clone(), from: owner, to: player }),
+        _ => None
+      })
+      .collect()
+    ),
+    (_, GameResult::Win(winner)) => {
+      let picks = cards_to_pick(game, rule, result);
+      Trade::taking(stakes(game, winner.other()).into_iter().take(picks).collect(), winner.other(), *winner)
+    }
+    (_, GameResult::Draw) => Trade::default()
+  }
+}
+
+// The trade if the game ended with the current score, so the AI can weigh what a position risks
+pub fn projected_trade(game:&Game, rule:TradeRule) -> Trade {
+  let result = match game.get_score(&Player::Blue) {
+    score if score > 0 => GameResult::Win(Player::Blue),
+    score if score < 0 => GameResult::Win(Player::Red),
+    _ => GameResult::Draw
+  };
+  compute_trade(game, rule, &result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{game::{GameCard, RuleSet, Square}, types::{CardStats, Coord}};
+
+  fn card(name:&str, stars:usize) -> Card {
+    Card { name: name.to_string(), id: 0, stars, stats: CardStats { top: 5, right: 5, bottom: 5, left: 5, tribe: None }, element: None }
+  }
+
+  // A finished game: Blue's five cards and four of Red's on the board, Blue having captured Red's 1* and 2*,
+  // and Red's 5* left in hand. Blue wins seven cards to three.
+  fn finished_game() -> Game {
+    let mut game = Game::new(Player::Blue, Player::Blue, RuleSet::new());
+    let owners = [Player::Blue, Player::Red].into_iter().flat_map(|owner| (1..=5).map(move |stars| (owner, stars)));
+    let mut squares = game.board.coords().collect::<Vec<Coord>>().into_iter();
+    for (owner, stars) in owners {
+      let player = if owner == Player::Red && stars <= 2 { Player::Blue } else { owner };
+      let card = GameCard { card: card(&format!("{} {}", owner, stars), stars), player: Some(player), owner: Some(owner) };
+      match squares.next() {
+        Some(coords) => *game.board.square_mut(&coords) = Square(Some(card), None),
+        None => { game.add_card_to_hand(card, owner); }
+      }
+    }
+    game
+  }
+
+  fn names(cards:Vec<&Card>) -> Vec<String> {
+    cards.into_iter().map(|card| card.name.clone()).collect()
+  }
+
+  #[test]
+  fn one_takes_the_losers_best_card() {
+    let trade = compute_trade(&finished_game(), TradeRule::One, &GameResult::Win(Player::Blue));
+    assert_eq!(names(trade.gained(Player::Blue)), ["R 5"]);
+    assert_eq!(trade.value_for(Player::Blue), 5);
+    assert_eq!(trade.value_for(Player::Red), -5);
+  }
+
+  #[test]
+  fn diff_takes_the_score_difference() {
+    let trade = compute_trade(&finished_game(), TradeRule::Diff, &GameResult::Win(Player::Blue));
+    assert_eq!(names(trade.gained(Player::Blue)), ["R 5", "R 4", "R 3", "R 2"]);
+  }
+
+  #[test]
+  fn direct_swaps_captured_cards_win_or_lose() {
+    for result in [GameResult::Win(Player::Blue), GameResult::Draw, GameResult::Win(Player::Red)] {
+      let trade = compute_trade(&finished_game(), TradeRule::Direct, &result);
+      assert_eq!(names(trade.gained(Player::Blue)), ["R 1", "R 2"]);
+      assert!(trade.gained(Player::Red).is_empty());
+    }
+  }
+
+  #[test]
+  fn all_takes_every_card() {
+    let trade = compute_trade(&finished_game(), TradeRule::All, &GameResult::Win(Player::Blue));
+    assert_eq!(names(trade.lost(Player::Red)), ["R 5", "R 4", "R 3", "R 2", "R 1"]);
+    assert_eq!(trade.value_for(Player::Blue), 15);
+  }
+
+  #[test]
+  fn draws_trade_nothing() {
+    for rule in [TradeRule::One, TradeRule::Diff, TradeRule::All] {
+      assert_eq!(compute_trade(&finished_game(), rule, &GameResult::Draw), Trade::default());
+    }
+  }
+
+  #[test]
+  fn unidentified_cards_are_worth_nothing() {
+    let trade = Trade::taking(vec![card("Unidentified", 0), card("R 3", 3)], Player::Red, Player::Blue);
+    assert_eq!(trade.value_for(Player::Blue), 3);
+  }
+}
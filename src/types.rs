@@ -3,60 +3,29 @@ use std::{str::FromStr, fmt};
 use colored::Colorize;
 
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-pub enum Row
-{ Top = 0
-, Middle = 1
-, Bottom = 2
-} impl Row {
-  pub fn idx(self) -> usize { self as usize }
-  pub fn from_idx(idx:usize) -> Option<Row> {
-    match idx {
-      0 => Some(Row::Top),
-      1 => Some(Row::Middle),
-      2 => Some(Row::Bottom),
-      _ => None
-    }
-  }
-}
-
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-pub enum Column
-{ Left = 0
-, Middle = 1
-, Right = 2
-} impl Column {
-  pub fn idx(self) -> usize { self as usize }
-  pub fn from_idx(idx:usize) -> Option<Column> {
-    match idx {
-      0 => Some(Column::Left),
-      1 => Some(Column::Middle),
-      2 => Some(Column::Right),
-      _ => None
-    }
-  }
-}
-
+// A zero-based board position; whether it's on the board depends on the board's size
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct Coord
-{ pub row: Row
-, pub col: Column
+{ pub row: usize
+, pub col: usize
 } impl FromStr for Coord {
-  type Err = std::num::ParseIntError;
+  type Err = String;
+  // Parses the one-based "row,col" format players type in
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    let coords: Vec<&str> = s.trim()
-                              .split(',')
-                              .collect();
-    let row = Row::from_idx(coords[0].parse::<usize>().unwrap() - 1).unwrap();
-    let col = Column::from_idx(coords[1].parse::<usize>().unwrap() - 1).unwrap();
-    Ok(Coord {row, col})
+    let coords = 
+      s.trim()
+      .split(',')
+      .map(|x| x.trim().parse::<usize>().ok().and_then(|x| x.checked_sub(1)))
+      .collect::<Vec<Option<usize>>>();
+    match coords[..] {
+      [Some(row), Some(col)] => Ok(Coord {row, col}),
+      _ => Err(format!("Couldn't read \"{}\" as coordinates; use \"row,col\" counting from 1", s.trim()))
+    }
   }
 } impl Coord {
-  pub fn from_numbers(row: usize, col: usize) -> Option<Self>{
-    match (Row::from_idx(row), Column::from_idx(col)) {
-      (Some(row), Some(col)) => Some(Coord {row,col}),
-      _ => None
-    }
+  pub fn new(row: usize, col: usize) -> Self {
+    Coord {row, col}
   }
 }
 
@@ -66,6 +35,8 @@ pub enum Tribe {
   Scion,
   Garlean,
   Primal,
+} impl Tribe {
+  pub fn idx(self) -> usize { self as usize }
 } impl fmt::Display for Tribe {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let out = match self {
@@ -87,11 +58,70 @@ pub struct CardStats
 , pub tribe: Option<Tribe>
 }
 
+impl CardStats {
+  // Applies a flat modifier to every side, keeping values within 1..=10
+  pub fn modified(&self, modifier:isize) -> CardStats {
+    let modify = |value:usize| (value as isize + modifier).clamp(1, 10) as usize;
+    CardStats
+      { top: modify(self.top)
+      , right: modify(self.right)
+      , bottom: modify(self.bottom)
+      , left: modify(self.left)
+      , tribe: self.tribe
+      }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Element
+{ Fire
+, Ice
+, Thunder
+, Earth
+, Poison
+, Wind
+, Water
+, Holy
+} impl Element {
+  pub const ALL: [Element; 8] = [Element::Fire, Element::Ice, Element::Thunder, Element::Earth, Element::Poison, Element::Wind, Element::Water, Element::Holy];
+} impl FromStr for Element {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "fire" => Ok(Element::Fire),
+      "ice" => Ok(Element::Ice),
+      "thunder" | "lightning" => Ok(Element::Thunder),
+      "earth" => Ok(Element::Earth),
+      "poison" => Ok(Element::Poison),
+      "wind" => Ok(Element::Wind),
+      "water" => Ok(Element::Water),
+      "holy" => Ok(Element::Holy),
+      _ => Err(format!("Unknown element \"{}\"", s.trim()))
+    }
+  }
+} impl fmt::Display for Element {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let out = match self {
+      Element::Fire => "F".red().to_string(),
+      Element::Ice => "I".cyan().to_string(),
+      Element::Thunder => "T".yellow().to_string(),
+      Element::Earth => "E".green().to_string(),
+      Element::Poison => "P".magenta().to_string(),
+      Element::Wind => "W".white().to_string(),
+      Element::Water => "≈".blue().to_string(),
+      Element::Holy => "H".bright_white().to_string()
+    };
+    write!(f, "{}", out)
+  }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct Card 
 { pub id: usize
 , pub name: String
 , pub stars: usize
 , pub stats: CardStats
+// Only FF8-style Elemental games use this
+, pub element: Option<Element>
 } 
 